@@ -1,13 +1,26 @@
-use std::{cmp::{max, min}, fs::{self, File}, io::{self, BufRead, BufReader}, path::Path, str};
-use crossterm::event::{self, Event, KeyEvent, KeyEventKind, KeyCode};
+use std::{cmp::min, collections::HashMap, path::{Path, PathBuf}, time::{Duration, Instant}};
+use crossterm::event::{Event, KeyEvent, KeyEventKind, KeyCode, KeyModifiers};
 use ratatui::{
-    buffer::Buffer, layout::{Constraint, Direction, Layout, Rect}, style::{Color, Style}, widgets::{Block, Borders, List, ListItem, Paragraph, Widget}, DefaultTerminal, Frame
+    backend::Backend, buffer::Buffer, layout::{Constraint, Direction, Layout, Position, Rect}, style::{Color, Modifier, Style}, text::{Line, Span}, widgets::{Block, List, ListItem, Paragraph, Widget, Wrap}, Frame, Terminal
 };
 use ansi_to_tui::IntoText;
-
-use crate::{engine::Engine, viewer};
+use syntect::highlighting::Theme;
+
+use crate::editor::LineEditor;
+use crate::engine::{Engine, InputSource};
+use crate::error::{Error, Result};
+use crate::events::{Events, Message};
+use crate::highlight::Highlighter;
+use crate::history::History;
+use crate::keymap::{Action, Keymap};
+use crate::preview::{Content, Preview, Previewer};
+use crate::setup::LayoutMode;
+use crate::theme::Colors;
 use crate::viewer::Viewer;
 
+pub const TICK_RATE: Duration = Duration::from_millis(80);
+const STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
 
 #[derive(PartialEq)]
 enum AppMode {
@@ -22,89 +35,354 @@ enum ViewerMode {
 }
 
 pub struct App {
-    search_string: String,
+    query: LineEditor,
     engine: Engine,
+    events: Events,
+    indexing: bool,
     exit: bool,
+    accepted: bool,
     selected_item_number: usize, 
     selected_item_name: String,
+    selected_item_id: Option<usize>,
+    marked: HashMap<usize, usize>, // file_ids picked with tab, to when they were marked
+    marks: usize,                   // made so far, they're printed in that order
 
     app_mode: AppMode,
-    viewer: Option<Viewer>,
-    update_viewer: bool,
+    previewer: Previewer,
+    preview: Option<Preview>, // may lag behind the selection while the next one loads
+    highlighter: Option<Highlighter>, // for the shown preview, lives here as it can't be sent between threads
+    requested_preview: String,
+    requested_at: Instant,
+    counting_lines: bool,
+    started: Instant,
+    status: Option<Status>, // the last non fatal error
+    colors: Colors,
+    theme: &'static Theme,
+    layout: LayoutMode,
+    keymap: Keymap,
+    history: History,
+    cursor: Option<Position>, // of the input box, set while drawing
+    list_height: usize,    // rows of the last frame, for paging
+    preview_height: usize,
+}
+
+struct Status {
+    error: Error,
+    more: usize, // errors that arrived while this one was shown
+    since: Instant,
 }
 
 impl App {
-    pub fn new(engine: Engine) -> Self {
-        App {search_string: String::new(),
-            engine: engine,
+    pub fn new(engine: Engine, events: Events) -> Self {
+        let previewer = Previewer::new(events.sender(), engine.setup());
+        let (colors, theme, layout) = (engine.setup().colors, engine.setup().theme, engine.setup().layout);
+        let keymap = engine.setup().keymap.clone();
+        let (history, history_error) = match History::open(engine.setup()) {
+            Ok(history) => (history, None),
+            Err(e) => (History::default(), Some(e)),
+        };
+
+        let mut app = App {query: LineEditor::default(),
+            engine,
+            events,
+            indexing: true,
             exit: false,
+            accepted: false,
             selected_item_number: 0,
             selected_item_name: String::new(),
+            selected_item_id: None,
+            marked: HashMap::new(),
+            marks: 0,
             app_mode: AppMode::Left,
-            viewer: None,
-            update_viewer: false,
+            previewer,
+            preview: None,
+            highlighter: None,
+            requested_preview: String::new(),
+            requested_at: Instant::now(),
+            counting_lines: false,
+            started: Instant::now(),
+            status: None,
+            colors,
+            theme,
+            layout,
+            keymap,
+            history,
+            cursor: None,
+            list_height: 0,
+            preview_height: 0,
+        };
+
+        if let Some(e) = history_error {
+            app.report(e);
         }
+        app
+    }
+
+    pub fn remember(&mut self, selected: &[String]) -> Result<()> {
+        // after an accept, the query goes to --history and the paths to the frecency database,
+        // piped lines only when they are paths
+        let saved = self.history.save(&self.query.text());
+        let walked = self.engine.setup().source == InputSource::Walk;
+        let paths: Vec<String> = selected.iter().filter(|name| walked || Path::new(name).exists()).cloned().collect();
+        self.engine.frecency().record(&paths).and(saved)
     }
 
-    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+    pub fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<Option<Vec<String>>> {
+        // returns the accepted paths, None when aborted
+        terminal.draw(|frame| self.draw(frame)).map_err(Error::Terminal)?;
+        self.sync_preview();
+
         while !self.exit {
-            terminal.draw(|frame| self.draw(frame))?;
-            self.handle_events()?;
+            // take everything that is pending, then draw once
+            let mut redraw = self.handle_message(self.events.next()?);
+            while let Some(message) = self.events.try_next() {
+                redraw |= self.handle_message(message);
+            }
+
+            if redraw && !self.exit {
+                terminal.draw(|frame| self.draw(frame)).map_err(Error::Terminal)?;
+                self.sync_preview();
+            }
+        }
+
+        if !self.accepted {
+            return Ok(None);
         }
 
-        Ok(())
+        match self.marked.is_empty() {
+            true => Ok(Some(vec![self.selected_item_name.clone()])),
+            false => Ok(Some(self.marked_names())),
+        }
+    }
+
+    fn marked_names(&self) -> Vec<String> {
+        // in the order they were marked, ids only say when the walker found them
+        let mut marked: Vec<(usize, usize)> = self.marked.iter().map(|(&id, &mark)| (mark, id)).collect();
+        marked.sort_unstable();
+        marked.into_iter().map(|(_, id)| self.engine.get_name(id).to_string()).collect()
     }
 
     fn draw(&mut self, frame: &mut Frame) {
-        frame.render_widget(self, frame.area());
+        frame.render_widget(&mut *self, frame.area());
+        if let Some(cursor) = self.cursor {
+            frame.set_cursor_position(cursor);
+        }
     }
 
-    fn handle_events(&mut self) -> io::Result<()> {
-        match event::read()? {
-            Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                self.handle_key_event(key_event)
+    fn handle_message(&mut self, message: Message) -> bool {
+        // returns true when the screen needs a redraw
+        match message {
+            Message::Input(Event::Key(key_event)) if key_event.kind == KeyEventKind::Press => {
+                self.handle_key_event(key_event);
+                true
             }
-            _ => {}
-        };
+            Message::Input(Event::Paste(text)) => {
+                self.paste(&text);
+                true
+            }
+            Message::Input(Event::Resize(_, _)) => true,
+            Message::Input(_) => false,
+            Message::Tick => self.poll_search() | self.expire_status() | self.highlight_ahead() || self.indexing || self.preview_pending() || self.preview_indexing(), // keeps the spinner going
+            Message::Indexed(batch) => {
+                self.engine.extend(batch);
+                true
+            }
+            Message::IndexDone => {
+                self.indexing = false;
+                true
+            }
+            Message::FileChanged(path) => {
+                // re-read, the old content stays on screen meanwhile
+                if path.as_path() == Path::new(&self.selected_item_name) {
+                    self.previewer.load(&self.selected_item_name);
+                }
+                false
+            }
+            Message::PreviewReady(generation, preview) => self.show_preview(generation, *preview),
+            Message::Error(error) => {
+                self.report(error);
+                true
+            }
+        }
+    }
+
+    fn report(&mut self, error: Error) {
+        // the newest error is shown, the others are only counted
+        let more = self.status.as_ref().map_or(0, |status| status.more + 1);
+        self.status = Some(Status { error, more, since: Instant::now() });
+    }
+
+    fn expire_status(&mut self) -> bool {
+        // returns true when the status bar was cleared
+        if self.status.as_ref().is_some_and(|status| status.since.elapsed() >= STATUS_TIMEOUT) {
+            self.status = None;
+            return true;
+        }
+
+        false
+    }
+
+    fn preview_indexing(&mut self) -> bool {
+        // the line count of a big file is still unknown, once more when it just got known
+        let counting = self.viewer().is_some_and(|viewer| viewer.line_count().is_none());
+        std::mem::replace(&mut self.counting_lines, counting) || counting
+    }
 
-        Ok(())
+    fn poll_search(&mut self) -> bool {
+        // a search in a big file runs in the background, redrawn while it does and once it found something
+        self.viewer().is_some_and(|viewer| viewer.poll_search(Duration::ZERO) || viewer.is_searching())
+    }
+
+    fn highlight_ahead(&mut self) -> bool {
+        // a window far into a file is shown plain until the highlighter parsed its way there
+        let viewer = self.preview.as_mut().and_then(|preview| preview.content.viewer());
+        match (viewer, self.highlighter.as_mut()) {
+            (Some(viewer), Some(highlighter)) => viewer.highlight_ahead(highlighter),
+            _ => false,
+        }
+    }
+
+    fn preview_pending(&self) -> bool {
+        self.preview.as_ref().map(|p| p.path.as_str()) != Some(self.selected_item_name.as_str())
+    }
+
+    fn sync_preview(&mut self) {
+        // asks for a preview of the selection once it changed
+        if self.selected_item_name.is_empty() {
+            self.preview = None;
+        } else if self.preview_pending() && self.requested_preview != self.selected_item_name {
+            self.requested_preview = self.selected_item_name.clone();
+            self.requested_at = Instant::now();
+            self.previewer.load(&self.selected_item_name);
+        }
+    }
+
+    fn show_preview(&mut self, generation: u64, mut preview: Preview) -> bool {
+        if generation != self.previewer.generation() {
+            return false;
+        }
+
+        let watched = preview.content.viewer().is_some().then(|| PathBuf::from(&preview.path));
+        self.events.watch(watched);
+        self.highlighter = preview.content.viewer().and_then(|viewer| viewer.syntax()).map(|syntax| Highlighter::new(syntax, self.theme));
+
+        match self.preview.take() {
+            // reloaded after a change on disk, keep the scroll position
+            Some(Preview { path, content: Content::File(old) | Content::Directory(old) | Content::Command(old), .. }) if path == preview.path => {
+                if let Some(viewer) = preview.content.viewer() {
+                    viewer.display_start = old.display_start;
+                }
+            }
+            Some(old) => self.previewer.store(old),
+            None => {}
+        }
+
+        self.preview = Some(preview);
+        true
+    }
+
+    fn viewer(&mut self) -> Option<&mut Viewer> {
+        self.preview.as_mut().and_then(|preview| preview.content.viewer())
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) {
-        match self.app_mode {
-            AppMode::Left => match key_event.code {
-                KeyCode::Backspace => self.pop_char(),
-                KeyCode::Char(chr) => self.add_char(chr),
-                KeyCode::Up => self.up_char(),
-                KeyCode::Down => self.down_char(),
-                _ => {},
-            },
-            AppMode::Right(ViewerMode::Normal) => match key_event.code {
-                KeyCode::Up => self.viewer.as_mut().unwrap().up_char(),
-                KeyCode::Down => self.viewer.as_mut().unwrap().down_char(),
-                KeyCode::Char('/') => self.switch_viewer_mode(),
-                KeyCode::Char('n') => self.viewer.as_mut().unwrap().go_to_next_search(),
-                KeyCode::Char('N') => self.viewer.as_mut().unwrap().go_to_prev_search(),
-                _ => {},
+        // printable keys are typed into the query or the preview search, bindings apply to everything else
+        let typed = match key_event.code {
+            KeyCode::Char(chr) if !key_event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => Some(chr),
+            _ => None,
+        };
+
+        match (&self.app_mode, typed, key_event.code) {
+            (AppMode::Left, Some(chr), _) => return self.add_char(chr),
+            (AppMode::Right(ViewerMode::Search), Some(chr), _) => return if let Some(viewer) = self.viewer() { viewer.add_char(chr) },
+            (AppMode::Right(ViewerMode::Search), _, KeyCode::Backspace) => return if let Some(viewer) = self.viewer() { viewer.pop_char() },
+            (AppMode::Right(ViewerMode::Search), _, KeyCode::Enter) => return self.enter_char(),
+            _ => {},
+        }
+
+        for action in self.keymap.actions(&key_event).to_vec() {
+            self.perform(action);
+            if self.exit {
+                break;
+            }
+        }
+    }
+
+    fn perform(&mut self, action: Action) {
+        let list = self.app_mode == AppMode::Left;
+        let (list_page, preview_page) = (self.list_height.max(1), self.preview_height.max(1));
+
+        match action {
+            Action::Accept => self.accept(),
+            Action::Abort => self.exit(),
+            Action::SwitchPane => self.switch_app_mode(),
+            // the best match is at the bottom by default, at the top with --layout reverse
+            Action::Up if list => self.move_selection(1, true),
+            Action::Down if list => self.move_selection(1, false),
+            Action::PageUp if list => self.move_selection(list_page, true),
+            Action::PageDown if list => self.move_selection(list_page, false),
+            Action::Up | Action::PreviewUp => self.scroll_preview(1, true),
+            Action::Down | Action::PreviewDown => self.scroll_preview(1, false),
+            Action::PageUp | Action::PreviewPageUp => self.scroll_preview(preview_page, true),
+            Action::PageDown | Action::PreviewPageDown => self.scroll_preview(preview_page, false),
+            Action::Toggle => self.toggle_mark(),
+            Action::ToggleAll => self.invert_marks(),
+            Action::SelectAll => self.select_all(),
+            Action::DeselectAll => self.deselect_all(),
+            Action::ClearQuery if list => self.edit_query(LineEditor::clear),
+            Action::BackwardDeleteChar if list => self.edit_query(LineEditor::backspace),
+            Action::DeleteChar if list => self.edit_query(LineEditor::delete),
+            Action::BackwardChar if list => self.query.left(),
+            Action::ForwardChar if list => self.query.right(),
+            Action::BackwardWord if list => self.query.word_left(),
+            Action::ForwardWord if list => self.query.word_right(),
+            Action::BeginningOfLine if list => self.query.home(),
+            Action::EndOfLine if list => self.query.end(),
+            Action::BackwardKillWord if list => self.edit_query(LineEditor::delete_word),
+            Action::UnixLineDiscard if list => self.edit_query(LineEditor::kill_line),
+            Action::PrevHistory if list => if let Some(query) = self.history.older(&self.query.text()) {
+                self.recall(&query);
             },
-            AppMode::Right(ViewerMode::Search) => match key_event.code {
-                KeyCode::Backspace => self.viewer.as_mut().unwrap().pop_char(),
-                KeyCode::Char(chr) => self.viewer.as_mut().unwrap().add_char(chr),
-                KeyCode::Enter => self.enter_char(),
-                _ => {},
+            Action::NextHistory if list => if let Some(query) = self.history.newer() {
+                self.recall(&query);
             },
+            Action::Search if self.app_mode == AppMode::Right(ViewerMode::Normal) && self.ready_viewer().is_some() => self.switch_viewer_mode(),
+            Action::NextMatch if !list => if let Some(viewer) = self.ready_viewer() { viewer.go_to_next_search() },
+            Action::PrevMatch if !list => if let Some(viewer) = self.ready_viewer() { viewer.go_to_prev_search() },
+            _ => {},
         }
+    }
 
-        match key_event.code {
-                KeyCode::Esc => self.exit(),
-                KeyCode::Tab => self.switch_app_mode(),
-                _ => {},
+    fn ready_viewer(&mut self) -> Option<&mut Viewer> {
+        // the shown preview is stale while the next one loads
+        match self.preview_pending() {
+            true => None,
+            false => self.viewer(),
+        }
+    }
+
+    fn move_selection(&mut self, by: usize, up: bool) {
+        for _ in 0..by {
+            match up == (self.layout == LayoutMode::Default) {
+                true => self.up_char(),
+                false => self.down_char(),
+            }
+        }
+    }
+
+    fn scroll_preview(&mut self, by: usize, up: bool) {
+        if let Some(viewer) = self.ready_viewer() {
+            for _ in 0..by {
+                if up {viewer.up_char()} else {viewer.down_char()}
+            }
         }
     }
 
     fn enter_char(&mut self) {
         self.switch_viewer_mode();
-        self.viewer.as_mut().unwrap().search();
+        if let Some(viewer) = self.viewer() {
+            viewer.search();
+        }
     }
 
     fn switch_app_mode(&mut self) {
@@ -118,133 +396,281 @@ impl App {
     fn up_char(&mut self) {
         if self.selected_item_number + 1 < self.engine.results_size() {
             self.selected_item_number += 1; 
-            self.update_viewer = true;
         }
     }
 
     fn down_char(&mut self) {
         if self.selected_item_number > 0 {
             self.selected_item_number -= 1;
-            self.update_viewer = true;
         }
     }
 
-    fn pop_char(&mut self) {
-        self.search_string.pop();
-        self.engine.pop_char();
+    fn toggle_mark(&mut self) {
+        if let Some(id) = self.selected_item_id {
+            self.toggle(id);
+        }
+    }
+
+    fn toggle(&mut self, id: usize) {
+        if self.marked.remove(&id).is_none() {
+            self.marked.insert(id, self.marks);
+            self.marks += 1;
+        }
+    }
+
+    fn select_all(&mut self) {
+        for id in self.engine.result_ids().collect::<Vec<_>>() {
+            if !self.marked.contains_key(&id) {
+                self.toggle(id);
+            }
+        }
+    }
+
+    fn deselect_all(&mut self) {
+        self.marked.clear();
+    }
+
+    fn invert_marks(&mut self) {
+        for id in self.engine.result_ids().collect::<Vec<_>>() {
+            self.toggle(id);
+        }
     }
 
     fn add_char(&mut self, chr: char) {
-        self.search_string.push(chr);
-        self.engine.push_char(chr);
+        self.edit_query(|query| query.insert(chr));
+    }
+
+    fn paste(&mut self, text: &str) {
+        match self.app_mode {
+            AppMode::Left => self.edit_query(|query| query.insert_str(text)),
+            AppMode::Right(ViewerMode::Search) => if let Some(viewer) = self.viewer() {
+                viewer.search_string.extend(text.chars().filter(|c| !c.is_control()));
+            },
+            AppMode::Right(ViewerMode::Normal) => {},
+        }
+    }
+
+    fn edit_query(&mut self, edit: impl FnOnce(&mut LineEditor)) {
+        // an edited query is a new draft for --history
+        self.history.reset();
+        edit(&mut self.query);
+        self.engine.set_query(&self.query.text());
+    }
+
+    fn recall(&mut self, query: &str) {
+        self.query.set(query);
+        self.engine.set_query(&self.query.text());
     }
 
     fn exit(&mut self) {
         self.exit = true;
     }
 
+    fn accept(&mut self) {
+        if self.selected_item_name.is_empty() {
+            return;
+        }
+
+        self.accepted = true;
+        self.exit();
+    }
+
     fn handle_list_area(&mut self, list_area: &Rect, buf: &mut Buffer) {
         // get results fro engine
-        let h = max(0, list_area.height - 2) as usize;
-        let mut items_string: Vec<String> = self.engine.get_items(h);
+        let h = list_area.height.saturating_sub(2) as usize;
+        self.list_height = h;
+        let engine_items = self.engine.get_items(h);
         
+        let progress = match self.indexing {
+            true => {
+                let frame = self.started.elapsed().as_millis() / TICK_RATE.as_millis();
+                format!(" {}", SPINNER[frame as usize % SPINNER.len()])
+            }
+            false => String::new(),
+        };
+
+        let block = Block::bordered()
+            .title(format!("Files {}/{}{progress}", self.engine.results_size(), self.engine.items_size()))
+            .title(Line::from(format!("{}/{} selected", self.marked.len(), self.engine.items_size())).right_aligned())
+            .border_style(self.border_style(self.app_mode == AppMode::Left));
+
         // update data
-        if items_string.is_empty() {
+        if engine_items.is_empty() {
+            self.selected_item_id = None;
+            self.selected_item_name.clear();
+            block.render(*list_area, buf);
             return;
         }
 
-        self.selected_item_number = min(self.selected_item_number, items_string.len() - 1);
-        self.selected_item_name = items_string[self.selected_item_number].clone();
+        self.selected_item_number = min(self.selected_item_number, engine_items.len() - 1);
+        self.selected_item_name = engine_items[self.selected_item_number].name.clone();
+        self.selected_item_id = Some(engine_items[self.selected_item_number].file_id);
 
+        // marker gutter followed by the name with matched chars highlighted
+        let mut items_lines: Vec<Line> = engine_items
+            .into_iter()
+            .map(|item| {
+                let marker = if self.marked.contains_key(&item.file_id) {">"} else {" "};
+                let mut spans = vec![Span::raw(format!("{marker} "))];
+                spans.extend(Self::highlight_matches(item.name, &item.positions, self.colors.matched));
+                Line::from(spans)
+            })
+            .collect();
 
-        // draw top empty lines
-        for _ in 0..(h - items_string.len()) {
-            items_string.push(String::new()); 
-        }
+        // draw top empty lines, the reverse layout lists from the top instead
+        let selected = match self.layout {
+            LayoutMode::Default => {
+                items_lines.resize(h, Line::default());
+                items_lines.reverse();
+                h - self.selected_item_number - 1
+            }
+            LayoutMode::Reverse => self.selected_item_number,
+        };
 
-        let items: Vec<ListItem> = items_string
+        let items: Vec<ListItem> = items_lines
             .into_iter()
-            .rev()
             .enumerate()
             .map(|(i, s)| {
                 let mut item = ListItem::new(s);
-                if i == h - self.selected_item_number - 1 {
-                    item = item.style(Style::default().fg(Color::Yellow));
+                if i == selected {
+                    item = item.style(Style::default().fg(self.colors.highlight));
                 }
                 item
             })
             .collect();
 
-        let list = List::new(items) 
-            .block(
-                Block::bordered()
-                    .title("Files")
-                    .border_style(Style::default().fg(Color::White))
-            );
+        let list = List::new(items).block(block);
 
         list.render(*list_area, buf);
     }
 
-    fn is_file_utf8(path: &str) -> io::Result<bool> {
-        let bytes = fs::read(path)?;
-        Ok(str::from_utf8(&bytes).is_ok())
-    }
-
-    fn handle_right_area(&mut self, area: &Rect, buf: &mut Buffer) -> io::Result<()> {
-        if Path::new(&self.selected_item_name).is_dir() {
-            return Ok(())
+    fn highlight_matches(name: String, positions: &[usize], color: Color) -> Vec<Span<'static>> {
+        // splits name into runs of matched / unmatched chars, positions are char indices
+        let matched_style = Style::default().fg(color).add_modifier(Modifier::BOLD);
+        let mut spans = Vec::new();
+        let mut run = String::new();
+        let mut run_matched = false;
+
+        for (i, chr) in name.chars().enumerate() {
+            let matched = positions.binary_search(&i).is_ok();
+            if matched != run_matched && !run.is_empty() {
+                let text = std::mem::take(&mut run);
+                spans.push(if run_matched {Span::styled(text, matched_style)} else {Span::raw(text)});
+            }
+            run_matched = matched;
+            run.push(chr);
         }
 
-         if !Self::is_file_utf8(&self.selected_item_name)? {
-            return Ok(());
+        if !run.is_empty() {
+            spans.push(if run_matched {Span::styled(run, matched_style)} else {Span::raw(run)});
         }
 
-        if self.update_viewer {
-            self.viewer = Some(Viewer::new(&self.selected_item_name).unwrap());
-            self.update_viewer = false;
+        spans
+    }
+
+    fn border_style(&self, focused: bool) -> Style {
+        Style::default().fg(if focused {self.colors.border} else {self.colors.fg})
+    }
+
+    fn handle_right_area(&mut self, area: &Rect, buf: &mut Buffer) {
+        let border_style = self.border_style(self.app_mode != AppMode::Left);
+        let block = |title: String| Block::bordered().title(title).border_style(border_style);
+
+        if self.selected_item_name.is_empty() {
+            return;
         }
 
-        if let None = self.viewer {
-            self.update_viewer = true;
-            return Ok(())
+        // the previous preview stays up for a moment, so quick loads don't flicker
+        let late = self.requested_preview == self.selected_item_name && self.requested_at.elapsed() >= TICK_RATE;
+        if self.preview_pending() && (late || self.preview.is_none()) {
+            Paragraph::new("loading…").block(block("file content".to_string())).render(*area, buf);
+            return;
         }
 
-        // get content
         let h = area.height as usize;
-        let start = self.viewer.as_mut().unwrap().display_start;
-
-        let (lines, colored) = self.viewer
-            .as_mut()
-            .unwrap()
-            .get_lines(start, h);
-
-        let items: Vec<ListItem> = match colored {
-            true => lines 
-                .into_iter()
-                .map(|ansi_line| ListItem::new(ansi_line.into_text().unwrap()))
-                .collect(),
-            false => lines 
-                .into_iter()
-                .map(|line| ListItem::new(line))
-                .collect()
+        self.preview_height = h.saturating_sub(2);
+        let title = match &self.preview {
+            Some(Preview { content: Content::Directory(_), .. }) => "directory".to_string(),
+            Some(Preview { content: Content::Command(_), .. }) => "preview".to_string(),
+            Some(Preview { content: Content::File(viewer), .. }) if viewer.is_hex() && viewer.is_searching() => "hex dump [searching…]".to_string(),
+            Some(Preview { content: Content::File(viewer), .. }) if viewer.is_hex() => "hex dump".to_string(),
+            // the detected encoding, how many chars couldn't be decoded and the line count once known
+            Some(Preview { content: Content::File(viewer), .. }) => {
+                let lines = match (viewer.line_count(), viewer.is_searching()) {
+                    (Some(count), false) => format!("{count} lines"),
+                    (Some(count), true) => format!("{count} lines, searching…"),
+                    (None, _) => "counting lines…".to_string(),
+                };
+
+                match (viewer.encoding(), viewer.replaced()) {
+                    (Some(encoding), 0) => format!("file content [{encoding}, {lines}]"),
+                    (Some(encoding), replaced) => format!("file content [{encoding}, {replaced} replaced, {lines}]"),
+                    (None, _) => format!("file content [{lines}]"),
+                }
+            }
+            _ => "file content".to_string(),
         };
-        
-        let list = List::new(items)
-            .block(
-                Block::bordered()
-                    .title("file content")
-                    .border_style(Style::default().fg(Color::White))
-            );
 
-        list.render(*area, buf);
+        let list = match &mut self.preview {
+            Some(Preview { content: Content::File(viewer) | Content::Directory(viewer) | Content::Command(viewer), .. }) => {
+                // get content
+                let start = viewer.display_start;
+                let (lines, colored) = viewer.get_lines(start, h, self.highlighter.as_mut());
+
+                let items: Vec<ListItem> = match colored {
+                    true => lines 
+                        .into_iter()
+                        .map(|ansi_line| match ansi_line.into_text() {
+                            Ok(text) => ListItem::new(text),
+                            Err(_) => ListItem::new(ansi_line),
+                        })
+                        .collect(),
+                    false => lines 
+                        .into_iter()
+                        .map(ListItem::new)
+                        .collect()
+                };
+
+                List::new(items)
+            }
+            Some(Preview { content: Content::Line, path, .. }) => {
+                // not a path (e.g. text piped on stdin), preview the line itself
+                let paragraph = Paragraph::new(path.as_str())
+                    .wrap(Wrap { trim: false })
+                    .block(block("line".to_string()));
+
+                paragraph.render(*area, buf);
+                return;
+            }
+            _ => return,
+        };
 
-        Ok(())
+        list.block(block(title)).render(*area, buf);
     }
 }
 
 impl Widget for &mut App {
 
     fn render(self, area:Rect, buf: &mut Buffer) {
+        buf.set_style(area, Style::default().fg(self.colors.fg).bg(self.colors.bg));
+
+        // status bar at the bottom, only while there is something to report
+        let area = match &self.status {
+            Some(status) => {
+                let [area, status_area] = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(1), Constraint::Length(1)])
+                    .areas(area);
+
+                let more = if status.more > 0 {format!(" (+{} more)", status.more)} else {String::new()};
+                Paragraph::new(format!("{}{more}", status.error))
+                    .style(Style::default().fg(Color::Red))
+                    .render(status_area, buf);
+                area
+            }
+            None => area,
+        };
+
         // split page in half
         let [left, right] = Layout::default()
             .direction(Direction::Horizontal)
@@ -252,52 +678,154 @@ impl Widget for &mut App {
             .areas(area);
     
         // split left area
-        let [list_area, input_area] = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Min(1), Constraint::Length(3)])
-            .areas(left);
+        let [list_area, input_area] = match self.layout {
+            LayoutMode::Default => Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(1), Constraint::Length(3)])
+                .areas(left),
+            LayoutMode::Reverse => {
+                let [input_area, list_area] = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Length(3), Constraint::Min(1)])
+                    .areas(left);
+                [list_area, input_area]
+            }
+        };
         
         // fill list_area
         App::handle_list_area(self, &list_area, buf);
 
         // fill input area
-        let content = match self.app_mode {
-            AppMode::Left | AppMode::Right(ViewerMode::Normal) => self.search_string.as_str(),
-            AppMode::Right(ViewerMode::Search) => self.viewer.as_mut().unwrap().search_string.as_str(),
+        let (prompt, input_border) = (self.colors.prompt, self.border_style(false));
+        let (content, before_cursor) = match self.app_mode {
+            AppMode::Left | AppMode::Right(ViewerMode::Normal) => (self.query.text(), self.query.before_cursor()),
+            AppMode::Right(ViewerMode::Search) => {
+                let search = self.viewer().map_or(String::new(), |viewer| viewer.search_string.clone());
+                (search.clone(), search)
+            }
         };
 
+        // the text scrolls so the cursor stays inside the box
+        let width = input_area.width.saturating_sub(2);
+        let column = Line::from(before_cursor).width() as u16;
+        let scroll = column.saturating_sub(width.saturating_sub(1));
+        self.cursor = (self.app_mode != AppMode::Right(ViewerMode::Normal))
+            .then(|| Position::new(input_area.x + 1 + column - scroll, input_area.y + 1));
+
         let input = Paragraph::new(content)
-            .style(Style::default().fg(Color::Blue))
+            .scroll((0, scroll))
+            .style(Style::default().fg(prompt))
             .block(
                 Block::bordered()
                 .title("Input")
-                .border_style(Style::default().fg(Color::White))
+                .border_style(input_border)
             );
         input.render(input_area, buf);
 
         // fill right area
-        if let Err(e) = App::handle_right_area(self, &right ,buf) {
-            panic!("Error: {e}")
-        };
+        Block::bordered()
+            .border_style(self.border_style(self.app_mode != AppMode::Left))
+            .render(right, buf);
 
+        App::handle_right_area(self, &right, buf);
+    }
+}
 
-        // render borders
-        let block = |color: Color| {
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(color))
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setup::Setup;
+    use ratatui::backend::TestBackend;
+
+    fn app(names: &[&str]) -> App {
+        let args = vec!["prog".into(), ".".into()];
+        let mut engine = Engine::new(Setup::from_args(&args, true).unwrap());
+        engine.extend(names.iter().map(|name| name.to_string()).collect());
+        App::new(engine, Events::new(TICK_RATE))
+    }
 
-        block(Color::White).render(input_area, buf);
-        match self.app_mode {
-            AppMode::Left => {
-                block(Color::Blue).render(list_area, buf);
-                block(Color::White).render(right, buf);
-            }
-            AppMode::Right(_) => {
-                block(Color::White).render(list_area, buf);
-                block(Color::Blue).render(right, buf);
-            }
+    fn run_with_keys(mut app: App, keys: &[KeyEvent]) -> Option<Vec<String>> {
+        for &key in keys {
+            app.events.sender().send(Message::Input(Event::Key(key))).unwrap();
+        }
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 10)).unwrap();
+        app.run(&mut terminal).unwrap()
+    }
+
+    #[test]
+    fn accept_returns_the_selection() {
+        assert_eq!(run_with_keys(app(&["./a.rs"]), &[KeyCode::Enter.into()]), Some(vec!["./a.rs".to_string()]));
+    }
+
+    #[test]
+    fn abort_returns_nothing() {
+        assert_eq!(run_with_keys(app(&["./a.rs"]), &[KeyCode::Esc.into()]), None);
+        assert_eq!(run_with_keys(app(&["./a.rs"]), &[KeyCode::Char('x').into(), KeyCode::Esc.into()]), None);
+    }
+
+    #[test]
+    fn accept_returns_the_marked_items() {
+        let select_all = KeyEvent::new(KeyCode::Char('a'), KeyModifiers::ALT);
+        let mut names = run_with_keys(app(&["./a.rs", "./b.rs", "./c.rs"]), &[select_all, KeyCode::Enter.into()]).unwrap();
+        names.sort();
+        assert_eq!(names, ["./a.rs", "./b.rs", "./c.rs"]);
+    }
+
+    #[test]
+    fn marks_in_the_order_they_are_made() {
+        let mut app = app(&["./a.rs", "./b.rs", "./c.rs"]);
+        for id in [2, 0] {
+            app.selected_item_id = Some(id);
+            app.toggle_mark();
+        }
+        assert_eq!(app.marked_names(), ["./c.rs", "./a.rs"]);
+
+        app.toggle_mark();
+        app.select_all();
+        assert_eq!(app.marked_names()[0], "./c.rs");
+        assert_eq!(app.marked.len(), 3);
+
+        app.selected_item_id = Some(1);
+        app.toggle_mark();
+        app.invert_marks();
+        assert_eq!(app.marked_names(), ["./b.rs"]);
+
+        app.deselect_all();
+        assert!(app.marked.is_empty());
+    }
+
+    fn runs(name: &str, positions: &[usize]) -> Vec<(String, bool)> {
+        // the text of every span and whether it's shown as matched
+        App::highlight_matches(name.to_string(), positions, Color::Red)
+            .into_iter()
+            .map(|span| (span.content.to_string(), span.style.fg == Some(Color::Red)))
+            .collect()
+    }
+
+    #[test]
+    fn highlights_matched_chars() {
+        let run = |text: &str, matched| (text.to_string(), matched);
+
+        assert_eq!(runs("src/main.rs", &[4, 5, 9]), [run("src/", false), run("ma", true), run("in.", false), run("r", true), run("s", false)]);
+        assert_eq!(runs("żółw/ćma.rs", &[1, 2, 5]), [run("ż", false), run("ół", true), run("w/", false), run("ć", true), run("ma.rs", false)]);
+        assert_eq!(runs("abc", &[0, 1, 2]), [run("abc", true)]);
+        assert_eq!(runs("abc", &[]), [run("abc", false)]);
+        assert!(runs("", &[]).is_empty());
+    }
+
+    #[test]
+    fn renders_into_tiny_areas() {
+        let args = vec!["prog".into(), ".".into()];
+        let mut engine = Engine::new(Setup::from_args(&args, true).unwrap());
+        engine.extend(vec!["./a.rs".to_string(), "./b.rs".to_string()]);
+
+        let mut app = App::new(engine, Events::new(TICK_RATE));
+        app.report(Error::Vanished(PathBuf::from("./c.rs")));
+
+        for height in 0..8 {
+            let area = Rect::new(0, 0, 60, height);
+            (&mut app).render(area, &mut Buffer::empty(area));
         }
     }
 }
@@ -0,0 +1,139 @@
+use std::{env, fs, io, path::Path};
+use serde::Deserialize;
+use crate::{error::{Error, Result}, setup};
+
+pub const CONFIG_FILE: &str = "config.toml";
+pub const DEFAULT_OPTS: &str = "FZF_RS_DEFAULT_OPTS";
+
+// defaults shared through dotfiles, every key maps to a command line flag
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    depth: Option<u8>,
+    hidden: Option<bool>,
+    no_ignore: Option<bool>,
+    exclude: Vec<String>,
+    ignore_case: Option<bool>,
+    map_syntax: Vec<String>,
+    theme: Option<String>,
+    color: Option<String>,
+    layout: Option<String>,
+    preview: Option<String>,
+    bind: Vec<String>,
+    history: Option<String>,
+    history_size: Option<usize>,
+    frecency: Option<bool>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> std::result::Result<Option<Config>, String> {
+        // a missing file is no config at all
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("{}: {e}", path.display())),
+        };
+
+        toml::from_str(&text).map(Some).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut flag = |name: &str, value: Option<&str>| {
+            args.push(name.to_string());
+            args.extend(value.map(str::to_string));
+        };
+
+        if let Some(depth) = self.depth {
+            flag("-d", Some(&depth.to_string()));
+        }
+        match self.hidden {
+            Some(true) => flag("--hidden", None),
+            Some(false) => flag("--no-hidden", None),
+            None => {}
+        }
+        match self.no_ignore {
+            Some(true) => flag("--no-ignore", None),
+            Some(false) => flag("--ignore", None),
+            None => {}
+        }
+        for glob in &self.exclude {
+            flag("--exclude", Some(glob));
+        }
+        match self.ignore_case {
+            Some(true) => flag("--ignore-case", None),
+            Some(false) => flag("--no-ignore-case", None),
+            None => {}
+        }
+        for mapping in &self.map_syntax {
+            flag("--map-syntax", Some(mapping));
+        }
+        for (name, value) in [("--theme", &self.theme), ("--color", &self.color), ("--layout", &self.layout), ("--preview", &self.preview)] {
+            if let Some(value) = value {
+                flag(name, Some(value));
+            }
+        }
+        for spec in &self.bind {
+            flag("--bind", Some(spec));
+        }
+        if let Some(history) = &self.history {
+            flag("--history", Some(history));
+        }
+        if let Some(size) = self.history_size {
+            flag("--history-size", Some(&size.to_string()));
+        }
+        match self.frecency {
+            Some(true) => flag("--frecency", None),
+            Some(false) => flag("--no-frecency", None),
+            None => {}
+        }
+
+        args
+    }
+}
+
+pub fn default_args() -> Result<Vec<String>> {
+    // flags from the config file followed by the ones in $FZF_RS_DEFAULT_OPTS, the command line goes after both
+    let mut args = Vec::new();
+
+    if let Some(dir) = setup::config_dir() {
+        let config = Config::load(&dir.join(CONFIG_FILE)).map_err(Error::Config)?;
+        args.extend(config.map(|c| c.args()).unwrap_or_default());
+    }
+
+    if let Ok(opts) = env::var(DEFAULT_OPTS) {
+        let opts = shlex::split(&opts).ok_or_else(|| Error::Config(format!("{DEFAULT_OPTS} has unbalanced quotes")))?;
+        args.extend(opts);
+    }
+
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_config_into_flags() {
+        let config: Config = toml::from_str(r#"
+            depth = 3
+            hidden = true
+            no-ignore = false
+            exclude = ["target", "*.lock"]
+            ignore-case = false
+            layout = "reverse"
+            preview = "head -50 {}"
+        "#).unwrap();
+
+        assert_eq!(config.args(), [
+            "-d", "3", "--hidden", "--ignore", "--exclude", "target", "--exclude", "*.lock",
+            "--no-ignore-case", "--layout", "reverse", "--preview", "head -50 {}",
+        ]);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(toml::from_str::<Config>("depht = 3").is_err());
+        assert_eq!(Config::load(Path::new("/nonexistent/config.toml")), Ok(None));
+    }
+}
@@ -0,0 +1,164 @@
+// a single line of text with a cursor, readline style
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    chars: Vec<char>,
+    cursor: usize, // char index, chars.len() is past the end
+}
+
+impl LineEditor {
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn before_cursor(&self) -> String {
+        self.chars[..self.cursor].iter().collect()
+    }
+
+    pub fn insert(&mut self, chr: char) {
+        self.chars.insert(self.cursor, chr);
+        self.cursor += 1;
+    }
+
+    pub fn insert_str(&mut self, text: &str) {
+        // pasted text, line breaks become spaces
+        for chr in text.trim_end_matches(['\n', '\r']).chars() {
+            self.insert(if chr == '\n' || chr == '\r' {' '} else {chr});
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    pub fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.chars.len());
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.chars.len();
+    }
+
+    pub fn word_left(&mut self) {
+        self.cursor = self.word_start();
+    }
+
+    pub fn word_right(&mut self) {
+        // to the end of the current or next word
+        let is_word = |i: usize| self.chars.get(i).is_some_and(|c| c.is_alphanumeric());
+        while self.cursor < self.chars.len() && !is_word(self.cursor) {
+            self.cursor += 1;
+        }
+        while is_word(self.cursor) {
+            self.cursor += 1;
+        }
+    }
+
+    pub fn delete_word(&mut self) {
+        // ctrl-w, the word before the cursor and the spaces after it
+        let start = self.word_start_by(|c| !c.is_whitespace());
+        self.chars.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    pub fn kill_line(&mut self) {
+        // ctrl-u, everything before the cursor
+        self.chars.drain(..self.cursor);
+        self.cursor = 0;
+    }
+
+    pub fn set(&mut self, text: &str) {
+        // e.g. a recalled query, the cursor goes to the end
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    pub fn clear(&mut self) {
+        self.chars.clear();
+        self.cursor = 0;
+    }
+
+    fn word_start(&self) -> usize {
+        self.word_start_by(|c| c.is_alphanumeric())
+    }
+
+    fn word_start_by(&self, is_word: impl Fn(char) -> bool) -> usize {
+        let mut i = self.cursor;
+        while i > 0 && !is_word(self.chars[i - 1]) {
+            i -= 1;
+        }
+        while i > 0 && is_word(self.chars[i - 1]) {
+            i -= 1;
+        }
+        i
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(text: &str) -> LineEditor {
+        let mut editor = LineEditor::default();
+        editor.insert_str(text);
+        editor
+    }
+
+    #[test]
+    fn edits_at_the_cursor() {
+        let mut editor = editor("src main");
+        editor.home();
+        editor.right();
+        editor.insert('x');
+        editor.delete();
+        assert_eq!(editor.text(), "sxc main");
+
+        editor.end();
+        editor.backspace();
+        assert_eq!((editor.text().as_str(), editor.cursor()), ("sxc mai", 7));
+    }
+
+    #[test]
+    fn jumps_and_deletes_words() {
+        let mut editor = editor("./src/main.rs foo");
+        editor.word_left();
+        assert_eq!(editor.cursor(), 14);
+        editor.word_left();
+        editor.word_left();
+        assert_eq!(editor.before_cursor(), "./src/");
+        editor.word_right();
+        assert_eq!(editor.before_cursor(), "./src/main");
+
+        editor.end();
+        editor.delete_word();
+        assert_eq!(editor.text(), "./src/main.rs ");
+        editor.left();
+        editor.kill_line();
+        assert_eq!((editor.text().as_str(), editor.cursor()), (" ", 0));
+    }
+
+    #[test]
+    fn pastes_a_single_line() {
+        assert_eq!(editor("a\nb\r\n").text(), "a b");
+    }
+}
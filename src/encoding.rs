@@ -0,0 +1,195 @@
+use std::fmt;
+
+pub const SAMPLE_SIZE: usize = 8192;
+pub const REPLACEMENT: char = char::REPLACEMENT_CHARACTER;
+
+// text encodings the viewer can show, anything else is binary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8 { bom: bool },
+    Utf16Le { bom: bool },
+    Utf16Be { bom: bool },
+    Latin1,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, bom) = match self {
+            Encoding::Utf8 { bom } => ("UTF-8", *bom),
+            Encoding::Utf16Le { bom } => ("UTF-16LE", *bom),
+            Encoding::Utf16Be { bom } => ("UTF-16BE", *bom),
+            Encoding::Latin1 => ("Latin-1", false),
+        };
+
+        write!(f, "{name}{}", if bom {" BOM"} else {""})
+    }
+}
+
+impl Encoding {
+    pub fn unit(&self) -> usize {
+        // bytes per code unit, lines split on unit boundaries only
+        match self {
+            Encoding::Utf16Le { .. } | Encoding::Utf16Be { .. } => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn newline(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf16Le { .. } => b"\n\0",
+            Encoding::Utf16Be { .. } => b"\0\n",
+            _ => b"\n",
+        }
+    }
+
+    pub fn without_bom(self) -> Encoding {
+        // for decoding parts of a file past its start
+        match self {
+            Encoding::Utf8 { .. } => Encoding::Utf8 { bom: false },
+            Encoding::Utf16Le { .. } => Encoding::Utf16Le { bom: false },
+            Encoding::Utf16Be { .. } => Encoding::Utf16Be { bom: false },
+            Encoding::Latin1 => Encoding::Latin1,
+        }
+    }
+
+    pub fn bom_len(&self) -> usize {
+        match self {
+            Encoding::Utf8 { bom: true } => 3,
+            Encoding::Utf16Le { bom: true } | Encoding::Utf16Be { bom: true } => 2,
+            _ => 0,
+        }
+    }
+}
+
+pub fn detect(sample: &[u8]) -> Option<Encoding> {
+    // sniffs the first bytes of a file, None means binary
+    match sample {
+        [0xEF, 0xBB, 0xBF, ..] => return Some(Encoding::Utf8 { bom: true }),
+        [0xFF, 0xFE, ..] => return Some(Encoding::Utf16Le { bom: true }),
+        [0xFE, 0xFF, ..] => return Some(Encoding::Utf16Be { bom: true }),
+        _ => {}
+    }
+
+    // utf-16 text without a bom, mostly ascii, has every other byte zero
+    let half = (sample.len() / 2) as f64;
+    let zeros_at = |parity: usize| sample.iter().skip(parity).step_by(2).filter(|&&b| b == 0).count() as f64;
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    if half >= 2.0 && odd / half > 0.4 && even / half < 0.05 {
+        return Some(Encoding::Utf16Le { bom: false });
+    }
+    if half >= 2.0 && even / half > 0.4 && odd / half < 0.05 {
+        return Some(Encoding::Utf16Be { bom: false });
+    }
+
+    // control chars other than whitespace and escape are rare in text
+    let controls = sample.iter().filter(|&&b| b < 0x20 && !b"\t\n\r\x0c\x1b".contains(&b)).count();
+    if sample.contains(&0) || controls * 10 > sample.len() {
+        return None;
+    }
+
+    // the sample may end in the middle of a char
+    let (valid, invalid) = utf8_errors(sample);
+    if invalid == 0 {
+        return Some(Encoding::Utf8 { bom: false });
+    }
+
+    // valid multi byte chars or c1 controls mean utf-8 with a few bad bytes rather than latin-1
+    let c1 = sample.iter().any(|&b| (0x80..0xA0).contains(&b));
+    match valid > 0 || c1 {
+        true if invalid * 10 < sample.len() => Some(Encoding::Utf8 { bom: false }),
+        true => None,
+        false => Some(Encoding::Latin1),
+    }
+}
+
+pub(crate) fn utf8_errors(mut bytes: &[u8]) -> (usize, usize) {
+    // counts valid multi byte chars and invalid sequences, each one decodes to a REPLACEMENT
+    let (mut valid, mut invalid) = (0, 0);
+
+    loop {
+        let (good, rest) = match std::str::from_utf8(bytes) {
+            Ok(text) => (text, &[][..]),
+            Err(e) => {
+                let good = std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default();
+                match e.error_len() {
+                    Some(len) => {
+                        invalid += 1;
+                        (good, &bytes[e.valid_up_to() + len..])
+                    }
+                    None => (good, &[][..]), // cut off at the end
+                }
+            }
+        };
+
+        valid += good.chars().filter(|c| c.len_utf8() > 1).count();
+        if rest.is_empty() {
+            return (valid, invalid);
+        }
+        bytes = rest;
+    }
+}
+
+pub fn decode(bytes: &[u8], encoding: Encoding) -> String {
+    // lossy, bytes that can't be decoded become REPLACEMENT
+    let bytes = &bytes[encoding.bom_len().min(bytes.len())..];
+
+    match encoding {
+        Encoding::Utf8 { .. } => String::from_utf8_lossy(bytes).into_owned(),
+        Encoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+        Encoding::Utf16Le { .. } | Encoding::Utf16Be { .. } => {
+            let le = matches!(encoding, Encoding::Utf16Le { .. });
+            let units = bytes.chunks(2).map(|pair| match (pair, le) {
+                ([lo, hi], true) | ([hi, lo], false) => u16::from_le_bytes([*lo, *hi]),
+                _ => REPLACEMENT as u16,
+            });
+
+            char::decode_utf16(units).map(|c| c.unwrap_or(REPLACEMENT)).collect()
+        }
+    }
+}
+
+pub fn encode(text: &str, encoding: Encoding) -> Vec<u8> {
+    // e.g. a search string, chars latin-1 can't represent give nothing that could match
+    match encoding {
+        Encoding::Utf8 { .. } => text.as_bytes().to_vec(),
+        Encoding::Latin1 => text.chars().map(|c| u8::try_from(c as u32).ok()).collect::<Option<Vec<u8>>>().unwrap_or_default(),
+        Encoding::Utf16Le { .. } => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        Encoding::Utf16Be { .. } => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_boms_and_utf16() {
+        assert_eq!(detect(b"\xEF\xBB\xBFhi"), Some(Encoding::Utf8 { bom: true }));
+        assert_eq!(detect(b"\xFF\xFEh\0i\0"), Some(Encoding::Utf16Le { bom: true }));
+        assert_eq!(detect(b"h\0e\0l\0l\0o\0"), Some(Encoding::Utf16Le { bom: false }));
+        assert_eq!(detect(b"\0h\0e\0l\0l\0o"), Some(Encoding::Utf16Be { bom: false }));
+    }
+
+    #[test]
+    fn tells_latin1_from_broken_utf8() {
+        assert_eq!(detect("zażółć".as_bytes()), Some(Encoding::Utf8 { bom: false }));
+        assert_eq!(detect(b"caf\xE9 cr\xE8me"), Some(Encoding::Latin1));
+        assert_eq!(detect(&["zażółć ".as_bytes(), b"\xFF"].concat()).map(|e| e.to_string()), Some("UTF-8".to_string()));
+        assert_eq!(detect(b"\x7fELF\x02\x01\x01\0\0"), None);
+    }
+
+    #[test]
+    fn decodes_lossy() {
+        assert_eq!(decode(b"caf\xE9", Encoding::Latin1), "café");
+        assert_eq!(decode(b"ok\xFFok", Encoding::Utf8 { bom: false }), "ok\u{FFFD}ok");
+        assert_eq!(decode(b"\xFF\xFEh\0i\0", Encoding::Utf16Le { bom: true }), "hi");
+        assert_eq!(decode(b"\0h\0i", Encoding::Utf16Be { bom: false }), "hi");
+    }
+
+    #[test]
+    fn encodes_search_strings() {
+        assert_eq!(encode("hi", Encoding::Utf16Le { bom: true }), b"h\0i\0");
+        assert_eq!(encode("café", Encoding::Latin1), b"caf\xE9");
+        assert!(encode("zażółć", Encoding::Latin1).is_empty());
+    }
+}
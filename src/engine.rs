@@ -1,116 +1,278 @@
-use std::{cmp::min, fs, io, path::Path};
-use crate::setup::Setup;
+use std::cmp::Reverse;
+use crate::{frecency::Frecency, query::Query, setup::Setup};
+
+// where the candidates come from
+#[derive(Debug, PartialEq)]
+pub enum InputSource {
+    Walk,                   // walk setup.root_dir
+    Stdin { delimiter: u8 }, // one candidate per delimiter separated record
+}
 
 pub struct Engine {
     setup: Setup,
     base_layer: Vec<String>,
-    search_layers: Vec<Vec<SearchResult>>,
+    bonus: Vec<i32>, // frecency bonus of every candidate, empty without a database
+    frecency: Frecency,
+    search_layers: Vec<SearchLayer>, // one layer per char of query
+    query: String,
 }
 
+struct SearchLayer {
+    query: Query,
+    results: Vec<SearchResult>,
+}
+
+pub struct Item {
+    pub file_id: usize,
+    pub name: String,
+    pub positions: Vec<usize>, // matched char indices in name, ascending
+}
+
+#[derive(Clone)]
 pub struct SearchResult {
     pub file_id: usize,
-    pub search_start: u16, // idx where we can start new search
-    pub first_occ: usize,
+    pub score: i32,
 }
 
 
 
 impl Engine {
     pub fn results_size(&self) -> usize {
-        self.search_layers.last().unwrap().len()
+        self.search_layers.last().unwrap().results.len()
+    }
+
+    pub fn items_size(&self) -> usize {
+        self.base_layer.len()
+    }
+
+    pub fn result_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        self.search_layers.last().unwrap().results.iter().map(|r| r.file_id)
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn setup(&self) -> &Setup {
+        &self.setup
+    }
+
+    pub fn get_name(&self, file_id: usize) -> &str {
+        &self.base_layer[file_id]
     }
 
     pub fn new(setup: Setup) -> Self {
-        let mut engine = Engine {
-            setup: setup,
+        // base layer is filled by the indexer, see extend
+        Engine {
+            setup,
             base_layer: Vec::new(),
-            search_layers: Vec::new(), 
-        };
-        
-        // create base layer
-        let root_dir = engine.setup.root_dir.clone();
-        if let Err(e) = engine.find_all_files(Path::new(&root_dir), 0) {
-            panic!("error: {e}");
+            bonus: Vec::new(),
+            frecency: Frecency::default(),
+            search_layers: vec![SearchLayer { query: Query::default(), results: Vec::new() }], 
+            query: String::new(),
         }
+    }
 
-        // make first search_layer for ""
-        engine.search_layers.push(Vec::new());
-        for id in 0..engine.base_layer.len() {
-            engine.search_layers[0].push(SearchResult { file_id: id, search_start: 0, first_occ: usize::MAX });
-        }
+    pub fn set_frecency(&mut self, frecency: Frecency) {
+        // the bonus is computed once per candidate as it arrives, the ones already there get none
+        self.frecency = frecency;
+    }
 
-        engine
+    pub fn frecency(&mut self) -> &mut Frecency {
+        &mut self.frecency
     }
 
-    fn find_all_files(&mut self, path: &Path, deep: u8) -> io::Result<()> {
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let file_name = entry.path().to_string_lossy().to_string();
-            self.base_layer.push(file_name);
+    fn bonus(&self, file_id: usize) -> i32 {
+        self.bonus.get(file_id).copied().unwrap_or(0)
+    }
 
-            if entry.file_type()?.is_dir() && deep + 1 < self.setup.deep {
-                self.find_all_files(&entry.path(), deep + 1)?;
-            }
+    pub fn extend(&mut self, names: Vec<String>) {
+        // adds new candidates and filters them through every cached layer
+        let first_id = self.base_layer.len();
+        if !self.frecency.is_empty() {
+            self.bonus.resize(first_id, 0);
+            self.bonus.extend(names.iter().map(|name| self.frecency.bonus(name)));
         }
+        self.base_layer.extend(names);
+        let texts: Vec<Vec<char>> = self.base_layer[first_id..].iter().map(|name| name.chars().collect()).collect();
 
-        Ok(())
-    }
+        let bonus = |file_id: usize| self.bonus.get(file_id).copied().unwrap_or(0);
+        for layer in &mut self.search_layers {
+            let mut new_results: Vec<SearchResult> = texts
+                .iter()
+                .enumerate()
+                .filter_map(|(i, text)| layer.query.matches(text).map(|m| SearchResult { file_id: first_id + i, score: m.score + bonus(first_id + i) }))
+                .collect();
 
-    fn create_new_layer(&self, chr: char) -> Vec<SearchResult> {
-        // creates a new layer after adding new char
+            if layer.query.is_empty() {
+                layer.results.append(&mut new_results);
+                continue;
+            }
 
-        let mut new_layer: Vec<SearchResult> = Vec::new();
-        if let Some(layer) = self.search_layers.last() {
-            for element in layer {
-                let file_id = element.file_id as usize;
-                let start = element.search_start as usize;
+            let key = |r: &SearchResult| (Reverse(r.score), self.base_layer[r.file_id].len());
+            new_results.sort_by_key(key);
 
-                if let Some(rel_pos) = self.base_layer[file_id][start..].find(chr) {
-                    new_layer.push(SearchResult { file_id: element.file_id, search_start: element.search_start + rel_pos as u16 + 1, first_occ: min(element.first_occ, element.search_start as usize + rel_pos )});
+            // merge two sorted runs
+            let old_results = std::mem::take(&mut layer.results);
+            let mut merged = Vec::with_capacity(old_results.len() + new_results.len());
+            let mut new_results = new_results.into_iter().peekable();
+            for old in old_results {
+                while let Some(new) = new_results.next_if(|new| key(new) < key(&old)) {
+                    merged.push(new);
                 }
+                merged.push(old);
+            }
+            merged.extend(new_results);
+
+            layer.results = merged;
+        }
+    }
+
+    fn create_new_layer(&self, query: &Query) -> Vec<SearchResult> {
+        // creates a new layer for query, filtering the newest cached layer
+        // whose results are known to be a superset of the new ones
+
+        let source = self.search_layers
+            .iter()
+            .rev()
+            .find(|layer| query.narrows(&layer.query))
+            .expect("error: the base layer matches every query");
+
+        if query == &source.query {
+            return source.results.clone();
+        }
+
+        let mut new_layer: Vec<SearchResult> = Vec::new();
+        for element in &source.results {
+            let text: Vec<char> = self.base_layer[element.file_id].chars().collect();
+            if let Some(found) = query.matches(&text) {
+                new_layer.push(SearchResult { file_id: element.file_id, score: found.score + self.bonus(element.file_id) });
             }
         }
 
         new_layer
     }
 
-    pub fn get_items(&self, no_items: usize) -> Vec<String> {
-        // collects top no_items paths strings 
+    pub fn get_items(&self, no_items: usize) -> Vec<Item> {
+        // collects top no_items paths 
 
-        let mut names = Vec::<String>::new();
+        let mut items = Vec::<Item>::new();
 
         if let Some(layer) = self.search_layers.last() {
-            for search_result in layer {
-                if names.len() + 1 > no_items {
+            for search_result in &layer.results {
+                if items.len() + 1 > no_items {
                     break;
                 }
-                names.push(self.base_layer[search_result.file_id as usize].clone());
+                let name = self.base_layer[search_result.file_id].clone();
+                let text: Vec<char> = name.chars().collect();
+                let positions = layer.query.matches(&text).map_or_else(Vec::new, |m| m.positions);
+
+                items.push(Item { file_id: search_result.file_id, name, positions });
             }    
         };
 
-        names
+        items
     }
 
     pub fn push_char(&mut self, chr: char) {
-        let mut layer = self.create_new_layer(chr); 
+        self.query.push(chr);
+        let query = Query::parse(&self.query, self.setup.case);
+        let mut results = self.create_new_layer(&query); 
 
-        layer.sort_by(|a, b| {
-            let len_a = a.search_start as usize - a.first_occ;
-            let len_b = b.search_start as usize - b.first_occ;
-
-            if len_a == len_b {
-                self.base_layer[a.file_id].len().cmp(&self.base_layer[b.file_id].len())
-            } else {
-                len_a.cmp(&len_b)
-            }
-        });
+        // best score first, shorter paths win ties
+        results.sort_by_key(|r| (Reverse(r.score), self.base_layer[r.file_id].len()));
 
-        self.search_layers.push(layer);
+        self.search_layers.push(SearchLayer { query, results });
     }
 
     pub fn pop_char(&mut self) {
         if self.search_layers.len() > 1 {
             self.search_layers.pop();
+            self.query.pop();
+        }
+    }
+
+    pub fn set_query(&mut self, query: &str) {
+        // keeps the layers of the common prefix with the current query and rebuilds the rest
+        let common = self.query.chars().zip(query.chars()).take_while(|(a, b)| a == b).count();
+
+        while self.search_layers.len() > common + 1 {
+            self.pop_char();
         }
+        for chr in query.chars().skip(common) {
+            self.push_char(chr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine(names: &[&str]) -> Engine {
+        let args = vec!["prog".into(), ".".into()];
+        let mut engine = Engine::new(Setup::from_args(&args, true).unwrap());
+
+        engine.extend(names.iter().map(|name| name.to_string()).collect());
+        engine
+    }
+
+    fn names(engine: &Engine) -> Vec<String> {
+        engine.get_items(usize::MAX).into_iter().map(|item| item.name).collect()
+    }
+
+    #[test]
+    fn ranks_and_pops() {
+        let mut engine = engine(&["./my_app/index.rs", "./src/main.rs", "./README.md"]);
+
+        "main".chars().for_each(|chr| engine.push_char(chr));
+        assert_eq!(names(&engine), vec!["./src/main.rs", "./my_app/index.rs"]);
+
+        (0..4).for_each(|_| engine.pop_char());
+        assert_eq!(engine.results_size(), 3);
+    }
+
+    #[test]
+    fn filters_items_arriving_mid_query() {
+        let mut engine = engine(&["./my_app/index.rs", "./README.md"]);
+
+        "main".chars().for_each(|chr| engine.push_char(chr));
+        engine.extend(vec!["./src/main.rs".to_string(), "./docs/guide.md".to_string()]);
+        assert_eq!(names(&engine), vec!["./src/main.rs", "./my_app/index.rs"]);
+
+        engine.pop_char();
+        engine.pop_char();
+        assert_eq!(engine.results_size(), 2);
+    }
+
+    #[test]
+    fn sets_query_keeping_the_common_prefix() {
+        let mut engine = engine(&["./my_app/index.rs", "./src/main.rs", "./README.md", "./src/mod.rs"]);
+
+        engine.set_query("src mod");
+        assert_eq!(names(&engine), vec!["./src/mod.rs"]);
+
+        engine.set_query("src main");
+        assert_eq!(engine.query(), "src main");
+        assert_eq!(engine.search_layers.len(), "src main".len() + 1);
+        assert_eq!(names(&engine), vec!["./src/main.rs"]);
+
+        engine.set_query("");
+        assert_eq!(engine.results_size(), 4);
+    }
+
+    #[test]
+    fn boosts_frequently_accepted_paths() {
+        let mut frecency = Frecency::default();
+        frecency.record(&["./b/main.rs".to_string()]).unwrap();
+
+        let args = vec!["prog".into(), ".".into()];
+        let mut engine = Engine::new(Setup::from_args(&args, true).unwrap());
+        engine.extend(vec!["./c/main.rs".to_string()]);
+        engine.set_frecency(frecency);
+        engine.extend(vec!["./a/main.rs".to_string(), "./b/main.rs".to_string()]);
+
+        engine.set_query("main");
+        assert_eq!(names(&engine), vec!["./b/main.rs", "./c/main.rs", "./a/main.rs"]);
     }
 }
@@ -0,0 +1,74 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+
+// errors of the whole crate, the ones returned from setup or the main loop end the program,
+// the rest is reported in the status bar
+#[derive(Debug)]
+pub enum Error {
+    PermissionDenied(PathBuf),
+    Vanished(PathBuf),
+    BrokenSymlink(PathBuf),
+    Io(PathBuf, io::Error),
+    Walk(String),       // e.g. a symlink loop
+    BadPattern(String), // invalid --exclude glob
+    Config(String),     // bad flag, config file or $FZF_RS_DEFAULT_OPTS
+    Terminal(io::Error),
+    Disconnected, // the event threads are gone
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: impl AsRef<Path>, error: io::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(path),
+            io::ErrorKind::NotFound if fs::symlink_metadata(&path).is_ok() => Error::BrokenSymlink(path),
+            io::ErrorKind::NotFound => Error::Vanished(path),
+            _ => Error::Io(path, error),
+        }
+    }
+
+    pub fn walk(error: ignore::Error) -> Self {
+        match error {
+            ignore::Error::WithPath { path, err } => match *err {
+                ignore::Error::Io(error) => Error::io(path, error),
+                err => Error::Walk(format!("{}: {err}", path.display())),
+            },
+            ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => Error::walk(*err),
+            err => Error::Walk(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PermissionDenied(path) => write!(f, "permission denied: {}", path.display()),
+            Error::Vanished(path) => write!(f, "file vanished: {}", path.display()),
+            Error::BrokenSymlink(path) => write!(f, "broken symlink: {}", path.display()),
+            Error::Io(path, error) => write!(f, "{}: {error}", path.display()),
+            Error::Walk(message) => write!(f, "{message}"),
+            Error::BadPattern(message) => write!(f, "bad pattern: {message}"),
+            Error::Config(message) => write!(f, "{message}"),
+            Error::Terminal(error) => write!(f, "terminal: {error}"),
+            Error::Disconnected => write!(f, "event threads are gone"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_io_errors() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+
+        assert!(matches!(Error::io("/x", denied), Error::PermissionDenied(_)));
+        assert!(matches!(Error::io("/surely/not/here", missing), Error::Vanished(_)));
+    }
+}
@@ -0,0 +1,118 @@
+use std::{fs, path::PathBuf, sync::{mpsc::{self, Receiver, Sender}, Arc, Mutex}, thread, time::{Duration, SystemTime}};
+use crossterm::event::{self, Event};
+use crate::{error::{Error, Result}, preview::Preview};
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+// everything the main loop reacts to, background subsystems report through it
+pub enum Message {
+    Input(Event),
+    Tick,
+    Indexed(Vec<String>), // a batch of new candidates
+    IndexDone,
+    FileChanged(PathBuf),
+    PreviewReady(u64, Box<Preview>), // generation of the request and the loaded preview
+    Error(Error),                    // non fatal, shown in the status bar
+}
+
+pub struct Events {
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+    watched: Arc<Mutex<Option<PathBuf>>>,
+}
+
+impl Events {
+    pub fn new(tick_rate: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let watched = Arc::new(Mutex::new(None));
+
+        Events::spawn_input(sender.clone(), tick_rate);
+        Events::spawn_watcher(sender.clone(), Arc::clone(&watched));
+
+        Events { sender, receiver, watched }
+    }
+
+    pub fn sender(&self) -> Sender<Message> {
+        self.sender.clone()
+    }
+
+    pub fn next(&self) -> Result<Message> {
+        // blocks until something happens, the input thread ticks at least every tick_rate
+        self.receiver.recv().map_err(|_| Error::Disconnected)
+    }
+
+    pub fn try_next(&self) -> Option<Message> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn watch(&self, path: Option<PathBuf>) {
+        // reports FileChanged when path is modified on disk
+        *self.watched.lock().unwrap() = path;
+    }
+
+    fn spawn_input(sender: Sender<Message>, tick_rate: Duration) {
+        thread::spawn(move || loop {
+            let message = match event::poll(tick_rate) {
+                Ok(true) => match event::read() {
+                    Ok(event) => Message::Input(event),
+                    Err(_) => break,
+                },
+                Ok(false) => Message::Tick,
+                Err(_) => break,
+            };
+
+            if sender.send(message).is_err() {
+                break;
+            }
+        });
+    }
+
+    fn spawn_watcher(sender: Sender<Message>, watched: Arc<Mutex<Option<PathBuf>>>) {
+        thread::spawn(move || {
+            let mut last: Option<(PathBuf, Option<SystemTime>)> = None;
+
+            loop {
+                thread::sleep(WATCH_INTERVAL);
+
+                let Some(path) = watched.lock().unwrap().clone() else {
+                    last = None;
+                    continue;
+                };
+
+                // a file that vanished counts as changed too, reloading reports it
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+
+                let changed = matches!(&last, Some((p, t)) if *p == path && *t != modified);
+                last = Some((path.clone(), modified));
+
+                if changed && sender.send(Message::FileChanged(path)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TempPath;
+
+    #[test]
+    fn reports_changes_of_the_watched_file() {
+        let file = TempPath::file("watched", b"one\n");
+        let (sender, receiver) = mpsc::channel();
+        let watched = Arc::new(Mutex::new(Some(file.to_path_buf())));
+        Events::spawn_watcher(sender, watched);
+
+        // the first look only records the mtime
+        assert!(receiver.recv_timeout(WATCH_INTERVAL * 3).is_err());
+
+        let modified = SystemTime::now() + Duration::from_secs(60);
+        fs::File::options().write(true).open(&file).unwrap().set_modified(modified).unwrap();
+        match receiver.recv_timeout(WATCH_INTERVAL * 3) {
+            Ok(Message::FileChanged(path)) => assert_eq!(path, file.to_path_buf()),
+            _ => panic!("no FileChanged"),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::{env, fs, ops::Deref, path::{Path, PathBuf}, process};
+
+// a file or dir in the temp dir for a test, unique per process and name, removed on drop
+pub struct TempPath(PathBuf);
+
+impl TempPath {
+    pub fn new(name: &str) -> Self {
+        // nothing there yet, e.g. for a database a test creates
+        let path = env::temp_dir().join(format!("fzf-{name}-{}", process::id()));
+        remove(&path);
+        TempPath(path)
+    }
+
+    pub fn file(name: &str, bytes: &[u8]) -> Self {
+        let path = TempPath::new(name);
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    pub fn dir(name: &str) -> Self {
+        let path = TempPath::new(name);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+}
+
+impl Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        remove(&self.0);
+    }
+}
+
+fn remove(path: &Path) {
+    let _ = match path.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path),
+    };
+}
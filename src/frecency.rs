@@ -0,0 +1,181 @@
+use std::{collections::HashMap, env, fs, io, path::{Path, PathBuf}, process, time::{SystemTime, UNIX_EPOCH}};
+use crate::{error::{Error, Result}, scorer::SCORE_MATCH};
+
+pub const DB_FILE: &str = "frecency.tsv";
+const MAX_BONUS: i32 = SCORE_MATCH * 2; // a strong match still beats a frequent file
+const HOUR: u64 = 60 * 60;
+const MAX_AGE: u64 = 90 * 24 * HOUR; // pruned entries not opened for that long
+
+// how often and how recently paths were accepted, one "path<TAB>count<TAB>last" line each
+#[derive(Debug, Default)]
+pub struct Frecency {
+    path: Option<PathBuf>, // None with --no-frecency
+    cwd: PathBuf,          // relative candidates are resolved against it
+    entries: HashMap<PathBuf, (u32, u64)>, // accept count and the last time, in seconds
+}
+
+impl Frecency {
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut frecency = Frecency {
+            path: path.map(Path::to_path_buf),
+            cwd: env::current_dir().unwrap_or_default(),
+            entries: HashMap::new(),
+        };
+        frecency.reload()?;
+        Ok(frecency)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn bonus(&self, name: &str) -> i32 {
+        // added to the fuzzy score of a candidate, grows slowly with the frecency
+        let Some(&(count, last)) = self.entries.get(&self.absolute(name)) else {
+            return 0;
+        };
+
+        let score = frecency(count, last, now());
+        ((1.0 + score).ln() * SCORE_MATCH as f64 / 2.0).round().min(MAX_BONUS as f64) as i32
+    }
+
+    pub fn record(&mut self, names: &[String]) -> Result<()> {
+        // on top of what other sessions saved meanwhile
+        self.reload()?;
+        let now = now();
+        for name in names {
+            let entry = self.entries.entry(self.absolute(name)).or_insert((0, now));
+            *entry = (entry.0.saturating_add(1), now);
+        }
+
+        self.save()
+    }
+
+    pub fn prune(&mut self) -> Result<usize> {
+        // drops paths that are gone or weren't accepted for a long time, returns how many
+        self.reload()?;
+        let now = now();
+        let before = self.entries.len();
+        self.entries.retain(|path, &mut (_, last)| now.saturating_sub(last) < MAX_AGE && path.exists());
+
+        self.save()?;
+        Ok(before - self.entries.len())
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.entries.clear();
+        self.save()
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(Error::io(path, e)),
+        };
+
+        self.entries = text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.rsplitn(3, '\t');
+                let last = fields.next()?.parse().ok()?;
+                let count = fields.next()?.parse().ok()?;
+                Some((PathBuf::from(fields.next()?), (count, last)))
+            })
+            .collect();
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        // through a temp file, a crash never leaves a truncated database
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+        }
+
+        let text: String = self
+            .entries
+            .iter()
+            .map(|(entry, (count, last))| format!("{}\t{count}\t{last}\n", entry.display()))
+            .collect();
+
+        let temp = path.with_file_name(format!(".{}.{}", path.file_name().unwrap_or_default().to_string_lossy(), process::id()));
+        fs::write(&temp, text).map_err(|e| Error::io(&temp, e))?;
+        fs::rename(&temp, path).map_err(|e| Error::io(path, e))
+    }
+
+    fn absolute(&self, name: &str) -> PathBuf {
+        // lexically, so no candidate costs a syscall, "./" parts are dropped by components
+        self.cwd.join(name).components().collect()
+    }
+}
+
+fn frecency(count: u32, last: u64, now: u64) -> f64 {
+    // recent accepts weigh more, like z and zoxide
+    let weight = match now.saturating_sub(last) {
+        age if age < HOUR => 4.0,
+        age if age < 24 * HOUR => 2.0,
+        age if age < 7 * 24 * HOUR => 1.0,
+        _ => 0.5,
+    };
+
+    count as f64 * weight
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TempPath;
+
+    #[test]
+    fn weighs_recent_accepts_more() {
+        let now = 100 * 24 * HOUR;
+        assert_eq!(frecency(3, now - 60, now), 12.0);
+        assert_eq!(frecency(3, now - 2 * 24 * HOUR, now), 3.0);
+        assert_eq!(frecency(3, 0, now), 1.5);
+    }
+
+    #[test]
+    fn records_and_boosts_candidates() {
+        let path = TempPath::new("frecency");
+
+        let mut frecency = Frecency::load(Some(&path)).unwrap();
+        frecency.record(&["./gone/main.rs".to_string()]).unwrap();
+        frecency.record(&["gone/main.rs".to_string()]).unwrap();
+
+        let mut frecency = Frecency::load(Some(&path)).unwrap();
+        assert_eq!(frecency.entries.values().next().map(|e| e.0), Some(2));
+        assert!(frecency.bonus("./gone/main.rs") > 0);
+        assert!(frecency.bonus("./gone/main.rs") <= MAX_BONUS);
+        assert_eq!(frecency.bonus("./README.md"), 0);
+
+        assert_eq!(frecency.prune().unwrap(), 1);
+        assert!(Frecency::load(Some(&path)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_what_other_sessions_saved() {
+        let path = TempPath::new("frecency-sessions");
+        let mut first = Frecency::load(Some(&path)).unwrap();
+        let mut second = Frecency::load(Some(&path)).unwrap();
+
+        first.record(&["a.rs".to_string()]).unwrap();
+        second.record(&["b.rs".to_string(), "a.rs".to_string()]).unwrap();
+        first.record(&["b.rs".to_string()]).unwrap();
+
+        let frecency = Frecency::load(Some(&path)).unwrap();
+        assert_eq!(frecency.entries.get(&frecency.absolute("a.rs")).map(|e| e.0), Some(2));
+        assert_eq!(frecency.entries.get(&frecency.absolute("b.rs")).map(|e| e.0), Some(2));
+    }
+}
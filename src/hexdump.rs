@@ -0,0 +1,227 @@
+use std::{collections::VecDeque, fs::File, io, os::unix::fs::FileExt, sync::{mpsc::{self, Receiver, RecvTimeoutError}, Arc}, thread, time::Duration};
+
+pub const ROW_SIZE: u64 = 16;
+const PAGE_SIZE: u64 = 64 * 1024;
+const CACHED_PAGES: usize = 4;
+const SEARCH_CHUNK: u64 = 1024 * 1024;
+
+// xxd like view of a binary file, pages are read only when they are shown
+pub struct HexDump {
+    file: File,
+    len: u64,
+    pages: VecDeque<(u64, Vec<u8>)>, // page number and its bytes, most recent first
+}
+
+impl HexDump {
+    pub fn new(file: File) -> io::Result<Self> {
+        let len = file.metadata()?.len();
+        Ok(HexDump { file, len, pages: VecDeque::new() })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.len.div_ceil(ROW_SIZE) as usize
+    }
+
+    pub fn row(&mut self, row: usize) -> String {
+        let offset = row as u64 * ROW_SIZE;
+        match self.page(offset / PAGE_SIZE) {
+            Ok(page) => {
+                let start = (offset % PAGE_SIZE) as usize;
+                let end = page.len().min(start + ROW_SIZE as usize);
+                format_row(offset, &page[start.min(end)..end])
+            }
+            Err(e) => format!("{offset:08x}: {e}"),
+        }
+    }
+
+    fn page(&mut self, number: u64) -> io::Result<&[u8]> {
+        if let Some(i) = self.pages.iter().position(|(n, _)| *n == number) {
+            let page = self.pages.remove(i).unwrap_or_default();
+            self.pages.push_front(page);
+        } else {
+            let offset = number * PAGE_SIZE;
+            let mut bytes = vec![0; PAGE_SIZE.min(self.len.saturating_sub(offset)) as usize];
+            self.file.read_exact_at(&mut bytes, offset)?;
+
+            self.pages.push_front((number, bytes));
+            self.pages.truncate(CACHED_PAGES);
+        }
+
+        Ok(&self.pages[0].1)
+    }
+
+    pub fn search(&self, pattern: Vec<u8>, from: u64, forward: bool) -> io::Result<Search> {
+        Search::spawn(&self.file, self.len, pattern, from, forward, 1)
+    }
+}
+
+// a find running on a background thread, so a big file doesn't block the ui, it stops once dropped
+pub struct Search {
+    found: Receiver<Option<u64>>,
+    _alive: Arc<()>, // the thread gives up once this is gone
+}
+
+impl Search {
+    pub fn spawn(file: &File, len: u64, pattern: Vec<u8>, from: u64, forward: bool, step: u64) -> io::Result<Self> {
+        let file = file.try_clone()?;
+        let alive = Arc::new(());
+        let weak = Arc::downgrade(&alive);
+        let (sender, found) = mpsc::channel();
+
+        thread::spawn(move || {
+            let cancelled = || weak.strong_count() == 0;
+            let _ = sender.send(find(&file, len, &pattern, from, forward, step, &cancelled).ok().flatten());
+        });
+
+        Ok(Search { found, _alive: alive })
+    }
+
+    pub fn poll(&self, wait: Duration) -> Option<Option<u64>> {
+        // None while still searching, then the offset of the match if there is one
+        match self.found.recv_timeout(wait) {
+            Ok(found) => Some(found),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(None),
+        }
+    }
+}
+
+pub fn find(file: &File, len: u64, pattern: &[u8], from: u64, forward: bool, step: u64, cancelled: &dyn Fn() -> bool) -> io::Result<Option<u64>> {
+    // offset of the nearest match starting at or after (at or before when !forward) from, wrapping around,
+    // only offsets that are a multiple of step count (e.g. utf-16 code units)
+    if pattern.is_empty() || pattern.len() as u64 > len {
+        return Ok(None);
+    }
+
+    let last = len - pattern.len() as u64;
+    let from = if forward && from > last {0} else {from.min(last)};
+
+    let found = match forward {
+        true => scan(file, pattern, from, last, true, step, cancelled)?,
+        false => scan(file, pattern, 0, from, false, step, cancelled)?,
+    };
+
+    match (found, forward) {
+        (Some(offset), _) => Ok(Some(offset)),
+        (None, true) if from > 0 => scan(file, pattern, 0, from - 1, true, step, cancelled),
+        (None, false) if from < last => scan(file, pattern, from + 1, last, false, step, cancelled),
+        (None, _) => Ok(None),
+    }
+}
+
+fn scan(file: &File, pattern: &[u8], start: u64, end: u64, forward: bool, step: u64, cancelled: &dyn Fn() -> bool) -> io::Result<Option<u64>> {
+    // matches starting within start..=end, read in chunks that overlap by pattern.len() - 1
+    let overlap = pattern.len() as u64 - 1;
+    let mut chunks = Vec::new();
+    let mut chunk_start = start;
+    while chunk_start <= end {
+        chunks.push(chunk_start);
+        chunk_start += SEARCH_CHUNK;
+    }
+    if !forward {
+        chunks.reverse();
+    }
+
+    let mut buf = Vec::new();
+    for chunk_start in chunks {
+        if cancelled() {
+            return Ok(None);
+        }
+
+        let chunk_end = (chunk_start + SEARCH_CHUNK - 1).min(end);
+        buf.resize((chunk_end - chunk_start + 1 + overlap) as usize, 0);
+        file.read_exact_at(&mut buf, chunk_start)?;
+
+        let mut hits = buf
+            .windows(pattern.len())
+            .enumerate()
+            .map(|(i, window)| (chunk_start + i as u64, window))
+            .filter(|(offset, window)| *window == pattern && offset % step == 0)
+            .map(|(offset, _)| offset);
+
+        let hit = if forward {hits.next()} else {hits.next_back()};
+        if hit.is_some() {
+            return Ok(hit);
+        }
+    }
+
+    Ok(None)
+}
+
+fn format_row(offset: u64, bytes: &[u8]) -> String {
+    // 00000010: 4865 6c6c 6f0a 0000 ...  Hello...
+    let mut hex = String::new();
+    for (i, byte) in bytes.iter().enumerate() {
+        hex.push_str(&format!("{byte:02x}"));
+        if i % 2 == 1 {
+            hex.push(' ');
+        }
+    }
+
+    let ascii: String = bytes
+        .iter()
+        .map(|&b| if b.is_ascii_graphic() || b == b' ' {b as char} else {'.'})
+        .collect();
+
+    format!("{offset:08x}: {hex:<40} {ascii}")
+}
+
+pub fn parse_pattern(query: &str) -> Vec<u8> {
+    // "0x7f454c46" or "7f 45 4c 46" are bytes, anything else is searched as text
+    let hex = match query.strip_prefix("0x") {
+        Some(digits) if !digits.is_empty() && digits.len() % 2 == 0 => Some(digits.to_string()),
+        Some(_) => None,
+        None if query.split(' ').count() > 1 && query.split(' ').all(|t| t.len() == 2) => Some(query.replace(' ', "")),
+        None => None,
+    };
+
+    let bytes = hex.and_then(|digits| {
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| digits.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+    });
+
+    bytes.unwrap_or_else(|| query.as_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TempPath;
+
+    fn dump(bytes: &[u8]) -> HexDump {
+        let path = TempPath::file(&format!("hex-{}", bytes.len()), bytes);
+        HexDump::new(File::open(&path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn formats_rows_like_xxd() {
+        let mut dump = dump(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0Hi");
+
+        assert_eq!(dump.rows(), 2);
+        assert_eq!(dump.row(0), "00000000: 7f45 4c46 0201 0100 0000 0000 0000 0000  .ELF............");
+        assert_eq!(dump.row(1), "00000010: 4869                                     Hi");
+    }
+
+    #[test]
+    fn parses_hex_patterns() {
+        assert_eq!(parse_pattern("0x7f45"), vec![0x7f, 0x45]);
+        assert_eq!(parse_pattern("4c 46"), vec![0x4c, 0x46]);
+        assert_eq!(parse_pattern("ELF"), b"ELF".to_vec());
+        assert_eq!(parse_pattern("0xzz"), b"0xzz".to_vec());
+    }
+
+    #[test]
+    fn finds_with_wrap_around() {
+        let dump = dump(b"ab..ab..ab");
+        let search = |pattern: &[u8], from, forward| dump.search(pattern.to_vec(), from, forward).unwrap().poll(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(search(b"ab", 1, true), Some(4));
+        assert_eq!(search(b"..", 9, true), Some(2));
+        assert_eq!(search(b"ab", 3, false), Some(0));
+        assert_eq!(search(b"ab", 0, false), Some(0));
+        assert_eq!(search(b"zz", 0, true), None);
+        assert_eq!(find(&dump.file, dump.len, b"ab", 1, true, 1, &|| true).unwrap(), None);
+    }
+}
@@ -0,0 +1,194 @@
+use std::{path::Path, time::{Duration, Instant}};
+use once_cell::sync::Lazy;
+use syntect::highlighting::{HighlightIterator, HighlightState, Highlighter as ThemeHighlighter, Style, Theme, ThemeSet};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxDefinition, SyntaxReference, SyntaxSet};
+use syntect::util::as_24_bit_terminal_escaped;
+
+pub static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(|| {
+    // syntect's defaults plus the bundled ones they lack
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    for syntax in BUNDLED_SYNTAXES {
+        builder.add(SyntaxDefinition::load_from_str(syntax, true, None).expect("bundled syntaxes are valid"));
+    }
+    builder.build()
+});
+pub static THEME_SET: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+
+const BUNDLED_SYNTAXES: &[&str] = &[include_str!("../assets/syntaxes/Dockerfile.sublime-syntax")];
+const CHECKPOINT_EVERY: usize = 128;
+const MAX_CATCH_UP: usize = 1024; // lines parsed within a frame, a window farther away is shown plain meanwhile
+const CATCH_UP_BUDGET: Duration = Duration::from_millis(15); // of parsing per tick towards that window
+
+pub fn detect(path: &str, first_line: &str, map_syntax: &[(String, String)]) -> Option<&'static SyntaxReference> {
+    // by the user's mapping, the whole file name (Makefile, .bashrc), the extension, then the first line (shebangs)
+    let name = Path::new(path).file_name()?.to_str()?;
+    let extension = Path::new(name).extension().and_then(|e| e.to_str());
+
+    let mapped = map_syntax
+        .iter()
+        .find(|(key, _)| key == name || Some(key.as_str()) == extension)
+        .and_then(|(_, syntax)| SYNTAX_SET.find_syntax_by_token(syntax));
+
+    mapped
+        .or_else(|| SYNTAX_SET.find_syntax_by_extension(name))
+        .or_else(|| extension.and_then(|e| SYNTAX_SET.find_syntax_by_extension(e)))
+        .or_else(|| SYNTAX_SET.find_syntax_by_first_line(first_line))
+}
+
+// syntect state before some line, enough to highlight the lines after it
+#[derive(Clone)]
+struct Checkpoint {
+    parse: ParseState,
+    highlight: HighlightState,
+}
+
+// highlights any window of a file, keeping the parser state every CHECKPOINT_EVERY lines
+// so multi line constructs are colored right and a frame only parses what's around it
+pub struct Highlighter {
+    syntax: &'static SyntaxReference,
+    theme: ThemeHighlighter<'static>,
+    checkpoints: Vec<Checkpoint>, // checkpoints[i] is the state before line i * CHECKPOINT_EVERY
+    wanted: usize,                // checkpoint of the last window
+}
+
+impl Highlighter {
+    pub fn new(syntax: &'static SyntaxReference, theme: &'static Theme) -> Self {
+        let theme = ThemeHighlighter::new(theme);
+        let mut highlighter = Highlighter { syntax, theme, checkpoints: Vec::new(), wanted: 0 };
+        highlighter.checkpoints.push(highlighter.fresh());
+        highlighter
+    }
+
+    fn fresh(&self) -> Checkpoint {
+        Checkpoint { parse: ParseState::new(self.syntax), highlight: HighlightState::new(&self.theme, ScopeStack::new()) }
+    }
+
+    pub fn highlight(&mut self, start: usize, lines: &[String], mut read: impl FnMut(usize, usize) -> Vec<String>) -> Option<Vec<String>> {
+        // lines are the window starting at line start, read gives any other range of the file
+        // None while the window is too far past the known checkpoints, advance catches up with it
+        self.wanted = start / CHECKPOINT_EVERY;
+        if !self.within_reach() {
+            return None;
+        }
+
+        let (from, mut state) = self.catch_up(self.wanted, &mut read, None);
+
+        if from < start {
+            for line in read(from, start) {
+                self.step(&mut state, &line);
+            }
+        }
+
+        let mut highlighted = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            highlighted.push(self.step(&mut state, line));
+
+            // scrolling down extends the checkpoints for free
+            let next = start + i + 1;
+            if next.is_multiple_of(CHECKPOINT_EVERY) && next / CHECKPOINT_EVERY == self.checkpoints.len() {
+                self.checkpoints.push(state.clone());
+            }
+        }
+
+        Some(highlighted)
+    }
+
+    pub fn advance(&mut self, mut read: impl FnMut(usize, usize) -> Vec<String>) -> bool {
+        // parses towards a window highlight gave up on for CATCH_UP_BUDGET, true once it can be colored
+        if self.within_reach() {
+            return false;
+        }
+
+        self.catch_up(self.wanted, &mut read, Some(Instant::now() + CATCH_UP_BUDGET));
+        self.within_reach()
+    }
+
+    fn within_reach(&self) -> bool {
+        self.wanted.saturating_sub(self.checkpoints.len() - 1) * CHECKPOINT_EVERY <= MAX_CATCH_UP
+    }
+
+    fn catch_up(&mut self, wanted: usize, read: &mut impl FnMut(usize, usize) -> Vec<String>, deadline: Option<Instant>) -> (usize, Checkpoint) {
+        // the nearest checkpoint up to the wanted one and the line it's before, parses the lines in between once
+        while self.checkpoints.len() <= wanted && deadline.is_none_or(|deadline| Instant::now() < deadline) {
+            let last = self.checkpoints.len() - 1;
+            let lines = read(last * CHECKPOINT_EVERY, (last + 1) * CHECKPOINT_EVERY);
+            if lines.len() < CHECKPOINT_EVERY {
+                break;
+            }
+
+            let mut state = self.checkpoints[last].clone();
+            for line in lines {
+                self.step(&mut state, &line);
+            }
+            self.checkpoints.push(state);
+        }
+
+        let nearest = wanted.min(self.checkpoints.len() - 1);
+        (nearest * CHECKPOINT_EVERY, self.checkpoints[nearest].clone())
+    }
+
+    fn step(&self, state: &mut Checkpoint, line: &str) -> String {
+        // highlights one line and moves the state past it
+        let line = format!("{line}\n");
+        let Ok(ops) = state.parse.parse_line(&line, &SYNTAX_SET) else {
+            return line.trim_end_matches('\n').to_string();
+        };
+
+        let ranges: Vec<(Style, &str)> = HighlightIterator::new(&mut state.highlight, &ops, &line, &self.theme)
+            .map(|(style, text)| (style, text.trim_end_matches('\n')))
+            .collect();
+
+        as_24_bit_terminal_escaped(&ranges, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_syntax_without_extension() {
+        let name = |path: &str, first_line: &str| detect(path, first_line, &[]).map(|syntax| syntax.name.as_str());
+
+        assert_eq!(name("./Makefile", ""), Some("Makefile"));
+        assert_eq!(name("./Dockerfile", ""), Some("Dockerfile"));
+        assert_eq!(name("scripts/run", "#!/usr/bin/env python3"), Some("Python"));
+        assert_eq!(name("./dir.v2/file", ""), None);
+        assert_eq!(name("src/main.rs", "#!/bin/sh"), Some("Rust"));
+        assert_eq!(detect("x.conf", "", &[("conf".into(), "Bourne Again Shell (bash)".into())]).map(|s| s.name.as_str()), Some("Bourne Again Shell (bash)"));
+    }
+
+    #[test]
+    fn colors_a_window_like_the_whole_file() {
+        let syntax = SYNTAX_SET.find_syntax_by_extension("rs").unwrap();
+        let theme = &THEME_SET.themes["base16-ocean.dark"];
+        let lines: Vec<String> = ["/* a block", "comment */", "fn main() {}"]
+            .iter()
+            .chain(["// filler"; 300].iter())
+            .map(|s| s.to_string())
+            .collect();
+
+        let mut whole = Highlighter::new(syntax, theme);
+        let expected = whole.highlight(0, &lines, |a, b| lines[a..b].to_vec()).unwrap();
+
+        let mut window = Highlighter::new(syntax, theme);
+        assert_eq!(window.highlight(1, &lines[1..3], |a, b| lines[a..b].to_vec()).unwrap(), expected[1..3]);
+        assert_eq!(window.highlight(260, &lines[260..270], |a, b| lines[a..b].to_vec()).unwrap(), expected[260..270]);
+        assert_eq!(window.checkpoints.len(), 3);
+    }
+
+    #[test]
+    fn catches_up_with_far_windows_in_steps() {
+        let syntax = SYNTAX_SET.find_syntax_by_extension("rs").unwrap();
+        let theme = &THEME_SET.themes["base16-ocean.dark"];
+        let lines: Vec<String> = ["/* a block comment"].iter().chain(["filler"; 3000].iter()).map(|s| s.to_string()).collect();
+        let read = |a: usize, b: usize| lines[a..b.min(lines.len())].to_vec();
+        let expected = Highlighter::new(syntax, theme).highlight(0, &lines, read).unwrap();
+
+        let mut window = Highlighter::new(syntax, theme);
+        assert_eq!(window.highlight(2900, &lines[2900..2910], read), None);
+        while !window.advance(read) {}
+        assert!(!window.advance(read));
+        assert_eq!(window.highlight(2900, &lines[2900..2910], read).unwrap(), expected[2900..2910]);
+    }
+}
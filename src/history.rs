@@ -0,0 +1,214 @@
+use std::{collections::{HashMap, HashSet}, env, fs::{self, OpenOptions}, io::{self, Write}, path::{Path, PathBuf}, process};
+use crate::{engine::InputSource, error::{Error, Result}, setup::Setup};
+
+pub const DEFAULT_SIZE: usize = 1000;
+
+// accepted queries, one "root<TAB>query" line each, oldest first
+// every root dir recalls only its own queries and keeps max of them, sessions append so they don't drop each other's
+#[derive(Debug, Default)]
+pub struct History {
+    path: Option<PathBuf>, // None without --history
+    root: String,
+    max: usize,
+    entries: Vec<(String, String)>,
+    lines: usize, // in the file, duplicates included, it's compacted once they're twice the entries
+    recalled: Option<usize>, // index of the query shown in the input box
+    draft: String,           // what was typed before recalling
+}
+
+impl History {
+    pub fn open(setup: &Setup) -> Result<Self> {
+        // keyed by the walked dir, piped candidates by the current one
+        let root = match setup.source {
+            InputSource::Walk => fs::canonicalize(&setup.root_dir),
+            InputSource::Stdin { .. } => env::current_dir(),
+        };
+        let root = root.map_or_else(|_| setup.root_dir.clone(), |root| root.display().to_string());
+
+        History::load(setup.history.as_deref(), &root, setup.history_size)
+    }
+
+    pub fn load(path: Option<&Path>, root: &str, max: usize) -> Result<Self> {
+        let mut history = History { path: path.map(Path::to_path_buf), root: root.to_string(), max, ..History::default() };
+        let Some(path) = path else {
+            return Ok(history);
+        };
+
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(Error::io(path, e)),
+        };
+
+        history.lines = text.lines().count();
+        history.entries = compact(parse(&text), max);
+
+        Ok(history)
+    }
+
+    pub fn older(&mut self, current: &str) -> Option<String> {
+        // an older query of this root
+        let end = match self.recalled {
+            Some(i) => i,
+            None => {
+                self.draft = current.to_string();
+                self.entries.len()
+            }
+        };
+
+        let i = (0..end).rev().find(|&i| self.entries[i].0 == self.root)?;
+        self.recalled = Some(i);
+        Some(self.entries[i].1.clone())
+    }
+
+    pub fn newer(&mut self) -> Option<String> {
+        // a newer query of this root, past the newest one is the draft
+        let start = self.recalled? + 1;
+        match (start..self.entries.len()).find(|&i| self.entries[i].0 == self.root) {
+            Some(i) => {
+                self.recalled = Some(i);
+                Some(self.entries[i].1.clone())
+            }
+            None => {
+                self.recalled = None;
+                Some(std::mem::take(&mut self.draft))
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        // the query was edited, recalling starts over from the newest query
+        self.recalled = None;
+        self.draft.clear();
+    }
+
+    pub fn save(&mut self, query: &str) -> Result<()> {
+        // appends the query, duplicates and the oldest ones above max are dropped on load and when compacting
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if query.trim().is_empty() {
+            return Ok(());
+        }
+
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+        }
+
+        let (root, query) = (self.root.clone(), query.replace(['\t', '\n', '\r'], " "));
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(format!("{root}\t{query}\n").as_bytes()))
+            .map_err(|e| Error::io(path, e))?;
+
+        self.entries.push((root, query));
+        self.entries = compact(std::mem::take(&mut self.entries), self.max);
+
+        self.lines += 1;
+        if self.lines > 2 * self.entries.len().max(self.max) {
+            self.compact_file()?;
+        }
+        Ok(())
+    }
+
+    fn compact_file(&mut self) -> Result<()> {
+        // rewritten through a temp file, with what other sessions appended meanwhile
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let text = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        let entries = compact(parse(&text), self.max);
+        let text: String = entries.iter().map(|(root, query)| format!("{root}\t{query}\n")).collect();
+
+        let temp = path.with_file_name(format!(".{}.{}", path.file_name().unwrap_or_default().to_string_lossy(), process::id()));
+        fs::write(&temp, text).map_err(|e| Error::io(&temp, e))?;
+        fs::rename(&temp, path).map_err(|e| Error::io(path, e))?;
+
+        self.lines = entries.len();
+        Ok(())
+    }
+}
+
+fn parse(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| line.rsplit_once('\t'))
+        .map(|(root, query)| (root.to_string(), query.to_string()))
+        .collect()
+}
+
+fn compact(entries: Vec<(String, String)>, max: usize) -> Vec<(String, String)> {
+    // the last occurrence of every entry, the newest max of them for every root
+    let mut seen = HashSet::new();
+    let mut per_root: HashMap<String, usize> = HashMap::new();
+    let mut kept: Vec<(String, String)> = entries
+        .into_iter()
+        .rev()
+        .filter(|entry| seen.insert(entry.clone()))
+        .filter(|(root, _)| {
+            let count = per_root.entry(root.clone()).or_default();
+            *count += 1;
+            *count <= max
+        })
+        .collect();
+    kept.reverse();
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TempPath;
+
+    #[test]
+    fn recalls_queries_of_the_root() {
+        let mut history = History { root: "/a".into(), ..History::default() };
+        history.entries = vec![("/a".into(), "one".into()), ("/b".into(), "other".into()), ("/a".into(), "two".into())];
+
+        assert_eq!(history.older("draft").as_deref(), Some("two"));
+        assert_eq!(history.older("two").as_deref(), Some("one"));
+        assert_eq!(history.older("one"), None);
+        assert_eq!(history.newer().as_deref(), Some("two"));
+        assert_eq!(history.newer().as_deref(), Some("draft"));
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    fn starts_over_after_an_edit() {
+        let mut history = History { root: "/a".into(), ..History::default() };
+        history.entries = vec![("/a".into(), "one".into()), ("/a".into(), "two".into())];
+
+        assert_eq!(history.older("draft").as_deref(), Some("two"));
+        assert_eq!(history.older("two").as_deref(), Some("one"));
+        history.reset();
+
+        assert_eq!(history.newer(), None);
+        assert_eq!(history.older("one!").as_deref(), Some("two"));
+        assert_eq!(history.newer().as_deref(), Some("one!"));
+    }
+
+    #[test]
+    fn saves_without_duplicates_up_to_max() {
+        let path = TempPath::new("history");
+
+        let mut history = History::load(Some(&path), "/a", 2).unwrap();
+        let mut other = History::load(Some(&path), "/b", 2).unwrap();
+        for query in ["one", "two", "one", ""] {
+            history.save(query).unwrap();
+        }
+        other.save("three\tfour").unwrap();
+
+        // both sessions kept their queries, max applies to every root on its own
+        let entries = |history: History| history.entries.into_iter().map(|(root, query)| format!("{root} {query}")).collect::<Vec<_>>();
+        assert_eq!(entries(History::load(Some(&path), "/a", 2).unwrap()), ["/a two", "/a one", "/b three four"]);
+        assert_eq!(entries(History::load(Some(&path), "/a", 1).unwrap()), ["/a one", "/b three four"]);
+
+        // the file is compacted once it has more than twice the lines it keeps
+        history.save("two").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 5);
+        history.save("one").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "/b\tthree four\n/a\ttwo\n/a\tone\n");
+    }
+}
@@ -0,0 +1,227 @@
+use std::{io::{self, BufRead, BufReader, Read}, mem, path::PathBuf, sync::mpsc::{self, RecvTimeoutError, Sender}, thread, time::{Duration, Instant}};
+use ignore::{overrides::OverrideBuilder, WalkBuilder, WalkState};
+use crate::{engine::InputSource, error::{Error, Result}, events::Message, setup::Setup};
+
+const BATCH_SIZE: usize = 1024;
+const BATCH_TIMEOUT: Duration = Duration::from_millis(50); // a candidate waits at most that long for its batch
+
+fn batcher(sender: Sender<Message>) -> Sender<String> {
+    // collects candidates from the indexing threads and reports them in batches,
+    // sends IndexDone after the last one, once every clone of the returned sender is gone
+    let (candidates, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut deadline = Instant::now();
+
+        loop {
+            // a slow producer can't hold back what it already found
+            let received = match batch.is_empty() {
+                true => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                false => receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            };
+
+            match received {
+                Ok(name) => {
+                    if batch.is_empty() {
+                        deadline = Instant::now() + BATCH_TIMEOUT;
+                    }
+                    batch.push(name);
+                    if batch.len() < BATCH_SIZE && Instant::now() < deadline {
+                        continue;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            // nobody listens anymore, the producers stop once their sends fail
+            if sender.send(Message::Indexed(mem::take(&mut batch))).is_err() {
+                return;
+            }
+        }
+
+        if !batch.is_empty() {
+            let _ = sender.send(Message::Indexed(batch));
+        }
+        let _ = sender.send(Message::IndexDone);
+    });
+
+    candidates
+}
+
+pub fn spawn(setup: &Setup, sender: Sender<Message>) -> Result<()> {
+    // sends Message::IndexDone once every candidate was reported
+    match setup.source {
+        InputSource::Walk => walk(walker(setup)?, sender),
+        InputSource::Stdin { delimiter } => read(io::stdin(), delimiter, sender),
+    }
+
+    Ok(())
+}
+
+fn walker(setup: &Setup) -> Result<WalkBuilder> {
+    // honors .gitignore, .ignore, .git/info/exclude and global git excludes
+    // and skips hidden files, unless told otherwise
+    let respect_ignore = !setup.no_ignore;
+
+    let mut overrides = OverrideBuilder::new(&setup.root_dir);
+    for glob in &setup.exclude {
+        overrides.add(&format!("!{glob}")).map_err(|e| Error::BadPattern(e.to_string()))?;
+    }
+    let overrides = overrides.build().map_err(|e| Error::BadPattern(e.to_string()))?;
+
+    let mut walker = WalkBuilder::new(&setup.root_dir);
+    walker
+        .max_depth(Some(setup.deep as usize))
+        .hidden(!setup.hidden)
+        .ignore(respect_ignore)
+        .git_ignore(respect_ignore)
+        .git_global(respect_ignore)
+        .git_exclude(respect_ignore)
+        .parents(respect_ignore)
+        .overrides(overrides)
+        .filter_entry(move |entry| !respect_ignore || entry.file_name() != ".git");
+
+    Ok(walker)
+}
+
+fn walk(walker: WalkBuilder, sender: Sender<Message>) {
+    // parallel directory walk, every worker thread hands its entries to the batcher
+    let candidates = batcher(sender.clone());
+
+    thread::spawn(move || {
+        walker.build_parallel().run(|| {
+            let (candidates, sender) = (candidates.clone(), sender.clone());
+
+            Box::new(move |entry| {
+                // unreadable entries are reported and skipped, the rest of the tree is still useful
+                let listening = match entry {
+                    Ok(entry) => entry.depth() == 0 || candidates.send(entry.path().to_string_lossy().to_string()).is_ok(),
+                    Err(e) => sender.send(Message::Error(Error::walk(e))).is_ok(),
+                };
+
+                if listening {
+                    WalkState::Continue
+                } else {
+                    WalkState::Quit
+                }
+            })
+        });
+    });
+}
+
+fn read(reader: impl Read + Send + 'static, delimiter: u8, sender: Sender<Message>) {
+    // one candidate per delimiter separated record
+    let candidates = batcher(sender.clone());
+
+    thread::spawn(move || {
+        for record in BufReader::new(reader).split(delimiter) {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    let _ = sender.send(Message::Error(Error::io(PathBuf::from("stdin"), e)));
+                    break;
+                }
+            };
+
+            let line = String::from_utf8_lossy(&record);
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+
+            if !line.is_empty() && candidates.send(line.to_string()).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TempPath;
+    use std::{fs, io::{Cursor, Write}, os::unix::net::UnixStream, path::Path, sync::mpsc::Receiver};
+
+    fn indexed(receiver: &Receiver<Message>) -> Vec<String> {
+        // everything up to IndexDone, which has to be the last message
+        let mut names = Vec::new();
+        loop {
+            match receiver.recv_timeout(Duration::from_secs(10)).expect("no IndexDone") {
+                Message::Indexed(batch) => names.extend(batch),
+                Message::IndexDone => break,
+                _ => panic!("unexpected message"),
+            }
+        }
+
+        assert!(receiver.recv().is_err());
+        names
+    }
+
+    fn read_all(input: &[u8], delimiter: u8) -> Vec<String> {
+        let (sender, receiver) = mpsc::channel();
+        read(Cursor::new(input.to_vec()), delimiter, sender);
+        indexed(&receiver)
+    }
+
+    #[test]
+    fn splits_records() {
+        assert_eq!(read_all(b"a\nb c\r\n\n\r\nlast", b'\n'), vec!["a", "b c", "last"]);
+        assert_eq!(read_all(b"a\nb\0\0c\0", b'\0'), vec!["a\nb", "c"]);
+        assert!(read_all(b"", b'\n').is_empty());
+    }
+
+    #[test]
+    fn sends_everything_before_done() {
+        let input: String = (0..BATCH_SIZE * 3 + 1).map(|i| format!("{i}\n")).collect();
+        let names = read_all(input.as_bytes(), b'\n');
+
+        assert_eq!(names.len(), BATCH_SIZE * 3 + 1);
+        assert_eq!(names.last().unwrap(), &(BATCH_SIZE * 3).to_string());
+    }
+
+    #[test]
+    fn flushes_while_the_producer_stalls() {
+        let (sender, receiver) = mpsc::channel();
+        let (mut producer, reader) = UnixStream::pair().unwrap();
+        read(reader, b'\n', sender);
+
+        producer.write_all(b"first\nsecond\n").unwrap();
+        match receiver.recv_timeout(BATCH_TIMEOUT * 10) {
+            Ok(Message::Indexed(batch)) => assert_eq!(batch, vec!["first", "second"]),
+            _ => panic!("the batch waits for more input"),
+        }
+
+        producer.write_all(b"third\n").unwrap();
+        drop(producer);
+        assert_eq!(indexed(&receiver), vec!["third"]);
+    }
+
+    fn walk_all(root: &Path, flags: &[&str]) -> Vec<String> {
+        // relative to root, sorted since the walk is parallel
+        let mut args = vec!["prog".to_string(), root.display().to_string()];
+        args.extend(flags.iter().map(|flag| flag.to_string()));
+        let setup = Setup::from_args(&args, true).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        walk(walker(&setup).unwrap(), sender);
+        let mut names: Vec<String> = indexed(&receiver).iter().map(|name| Path::new(name).strip_prefix(root).unwrap().display().to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn walks_like_git_would() {
+        let root = TempPath::dir("walk");
+        for dir in [".git", "sub"] {
+            fs::create_dir(root.join(dir)).unwrap();
+        }
+        for (file, text) in [(".git/HEAD", ""), (".gitignore", "ignored.txt\n"), (".ignore", "also_ignored.txt\n"), (".hidden", ""), ("ignored.txt", ""), ("also_ignored.txt", ""), ("kept.rs", ""), ("sub/deep.rs", ""), ("sub/notes.log", "")] {
+            fs::write(root.join(file), text).unwrap();
+        }
+
+        assert_eq!(walk_all(&root, &[]), ["kept.rs", "sub", "sub/deep.rs", "sub/notes.log"]);
+        assert_eq!(walk_all(&root, &["--hidden"]), [".gitignore", ".hidden", ".ignore", "kept.rs", "sub", "sub/deep.rs", "sub/notes.log"]);
+        assert_eq!(walk_all(&root, &["--no-ignore"]), ["also_ignored.txt", "ignored.txt", "kept.rs", "sub", "sub/deep.rs", "sub/notes.log"]);
+        assert_eq!(walk_all(&root, &["--exclude", "*.log", "-d", "1"]), ["kept.rs", "sub"]);
+        assert_eq!(walk_all(&root, &["--exclude", "sub"]), ["kept.rs"]);
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// everything a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Accept,
+    Abort,
+    Up,   // the selection, or the preview when it's focused
+    Down,
+    PageUp,
+    PageDown,
+    Toggle, // marks the selected candidate
+    ToggleAll,
+    SelectAll,
+    DeselectAll,
+    PreviewUp,
+    PreviewDown,
+    PreviewPageUp,
+    PreviewPageDown,
+    SwitchPane,
+    ClearQuery,
+    BackwardDeleteChar, // the query is edited at its cursor
+    DeleteChar,
+    BackwardChar,
+    ForwardChar,
+    BackwardWord,
+    ForwardWord,
+    BeginningOfLine,
+    EndOfLine,
+    BackwardKillWord,
+    UnixLineDiscard,
+    PrevHistory,
+    NextHistory,
+    Search, // in the preview
+    NextMatch,
+    PrevMatch,
+    Ignore,
+}
+
+const ACTIONS: &[(&str, Action)] = &[
+    ("accept", Action::Accept),
+    ("abort", Action::Abort),
+    ("up", Action::Up),
+    ("down", Action::Down),
+    ("page-up", Action::PageUp),
+    ("page-down", Action::PageDown),
+    ("toggle", Action::Toggle),
+    ("toggle-all", Action::ToggleAll),
+    ("select-all", Action::SelectAll),
+    ("deselect-all", Action::DeselectAll),
+    ("preview-up", Action::PreviewUp),
+    ("preview-down", Action::PreviewDown),
+    ("preview-page-up", Action::PreviewPageUp),
+    ("preview-page-down", Action::PreviewPageDown),
+    ("switch-pane", Action::SwitchPane),
+    ("clear-query", Action::ClearQuery),
+    ("backward-delete-char", Action::BackwardDeleteChar),
+    ("delete-char", Action::DeleteChar),
+    ("backward-char", Action::BackwardChar),
+    ("forward-char", Action::ForwardChar),
+    ("backward-word", Action::BackwardWord),
+    ("forward-word", Action::ForwardWord),
+    ("beginning-of-line", Action::BeginningOfLine),
+    ("end-of-line", Action::EndOfLine),
+    ("backward-kill-word", Action::BackwardKillWord),
+    ("unix-line-discard", Action::UnixLineDiscard),
+    ("prev-history", Action::PrevHistory),
+    ("next-history", Action::NextHistory),
+    ("search", Action::Search),
+    ("next-match", Action::NextMatch),
+    ("prev-match", Action::PrevMatch),
+    ("ignore", Action::Ignore),
+];
+
+const DEFAULT_BINDINGS: &str = "esc:abort,ctrl-c:abort,enter:accept,ctrl-o:switch-pane,\
+    up:up,down:down,pgup:page-up,pgdn:page-down,tab:toggle+up,btab:toggle+down,\
+    alt-a:select-all,alt-d:deselect-all,alt-t:toggle-all,bspace:backward-delete-char,\
+    del:delete-char,left:backward-char,right:forward-char,ctrl-b:backward-char,ctrl-f:forward-char,\
+    alt-b:backward-word,alt-f:forward-word,ctrl-a:beginning-of-line,home:beginning-of-line,\
+    ctrl-e:end-of-line,end:end-of-line,ctrl-w:backward-kill-word,ctrl-u:unix-line-discard,\
+    ctrl-p:prev-history,ctrl-n:next-history,\
+    shift-up:preview-up,shift-down:preview-down,/:search,n:next-match,N:prev-match";
+
+type Key = (KeyCode, KeyModifiers);
+
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<Key, Vec<Action>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap { bindings: HashMap::new() };
+        keymap.bind(DEFAULT_BINDINGS).expect("default bindings parse");
+        keymap
+    }
+}
+
+impl Keymap {
+    pub fn bind(&mut self, spec: &str) -> Result<(), String> {
+        // "ctrl-j:down,alt-k:up,tab:toggle+down", later bindings replace earlier ones
+        for binding in spec.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+            let (key, actions) = binding.split_once(':').ok_or_else(|| format!("{binding} is not key:action"))?;
+            let actions = actions.split('+').map(parse_action).collect::<Result<Vec<_>, _>>()?;
+            self.bindings.insert(parse_key(key)?, actions);
+        }
+
+        Ok(())
+    }
+
+    pub fn actions(&self, event: &KeyEvent) -> &[Action] {
+        self.bindings.get(&normalize(event.code, event.modifiers)).map_or(&[], Vec::as_slice)
+    }
+}
+
+fn normalize(code: KeyCode, modifiers: KeyModifiers) -> Key {
+    // shift is already part of chars and back tab
+    match code {
+        KeyCode::Char(_) | KeyCode::BackTab => (code, modifiers - KeyModifiers::SHIFT),
+        _ => (code, modifiers),
+    }
+}
+
+fn parse_action(name: &str) -> Result<Action, String> {
+    ACTIONS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|&(_, action)| action)
+        .ok_or_else(|| format!("unknown action {name}"))
+}
+
+fn parse_key(name: &str) -> Result<Key, String> {
+    // ctrl-, alt- and shift- chords, e.g. ctrl-alt-x
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    loop {
+        let (modifier, tail) = match rest.split_once('-') {
+            Some(("ctrl", tail)) if !tail.is_empty() => (KeyModifiers::CONTROL, tail),
+            Some(("alt", tail)) if !tail.is_empty() => (KeyModifiers::ALT, tail),
+            Some(("shift", tail)) if !tail.is_empty() => (KeyModifiers::SHIFT, tail),
+            _ => break,
+        };
+        modifiers |= modifier;
+        rest = tail;
+    }
+
+    let code = match rest {
+        "enter" | "return" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "btab" => KeyCode::BackTab,
+        "bspace" | "backspace" => KeyCode::Backspace,
+        "del" | "delete" => KeyCode::Delete,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pgup" | "page-up" => KeyCode::PageUp,
+        "pgdn" | "page-down" => KeyCode::PageDown,
+        "space" => KeyCode::Char(' '),
+        "comma" => KeyCode::Char(','),
+        "colon" => KeyCode::Char(':'),
+        "plus" => KeyCode::Char('+'),
+        f if f.len() > 1 && f.starts_with('f') && f[1..].parse::<u8>().is_ok_and(|n| (1..=12).contains(&n)) => {
+            KeyCode::F(f[1..].parse().unwrap_or(1))
+        }
+        c if c.chars().count() == 1 => KeyCode::Char(c.chars().next().unwrap_or_default()),
+        _ => return Err(format!("unknown key {name}")),
+    };
+
+    // shift is part of the char, shift-a is A
+    let code = match code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => match c.is_alphabetic() {
+            true => KeyCode::Char(c.to_uppercase().next().unwrap_or(c)),
+            false => return Err(format!("unknown key {name}, bind the shifted char itself")),
+        },
+        code => code,
+    };
+
+    Ok(normalize(code, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parses_chords_and_chains() {
+        let mut keymap = Keymap::default();
+        keymap.bind("ctrl-j:down,alt-k:up,ctrl-alt-x:clear-query+toggle,f5:accept").unwrap();
+
+        assert_eq!(keymap.actions(&key(KeyCode::Char('j'), KeyModifiers::CONTROL)), [Action::Down]);
+        assert_eq!(keymap.actions(&key(KeyCode::Char('k'), KeyModifiers::ALT)), [Action::Up]);
+        assert_eq!(keymap.actions(&key(KeyCode::Char('x'), KeyModifiers::CONTROL | KeyModifiers::ALT)), [Action::ClearQuery, Action::Toggle]);
+        assert_eq!(keymap.actions(&key(KeyCode::F(5), KeyModifiers::NONE)), [Action::Accept]);
+    }
+
+    #[test]
+    fn keeps_defaults_until_rebound() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.actions(&key(KeyCode::Tab, KeyModifiers::NONE)), [Action::Toggle, Action::Up]);
+        assert_eq!(keymap.actions(&key(KeyCode::BackTab, KeyModifiers::SHIFT)), [Action::Toggle, Action::Down]);
+        assert_eq!(keymap.actions(&key(KeyCode::Char('N'), KeyModifiers::SHIFT)), [Action::PrevMatch]);
+
+        keymap.bind("tab:toggle+down").unwrap();
+        assert_eq!(keymap.actions(&key(KeyCode::Tab, KeyModifiers::NONE)), [Action::Toggle, Action::Down]);
+    }
+
+    #[test]
+    fn rejects_bad_bindings() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.bind("ctrl-j"), Err("ctrl-j is not key:action".to_string()));
+        assert_eq!(keymap.bind("ctrl-j:jump"), Err("unknown action jump".to_string()));
+        assert_eq!(keymap.bind("hyper-j:up"), Err("unknown key hyper-j".to_string()));
+        assert_eq!(keymap.bind("shift-1:up"), Err("unknown key shift-1, bind the shifted char itself".to_string()));
+    }
+
+    #[test]
+    fn binds_shifted_letters_as_uppercase() {
+        let mut keymap = Keymap::default();
+        keymap.bind("shift-a:accept,ctrl-shift-b:toggle").unwrap();
+
+        assert_eq!(keymap.actions(&key(KeyCode::Char('A'), KeyModifiers::SHIFT)), [Action::Accept]);
+        assert_eq!(keymap.actions(&key(KeyCode::Char('B'), KeyModifiers::CONTROL | KeyModifiers::SHIFT)), [Action::Toggle]);
+        assert!(keymap.actions(&key(KeyCode::Char('a'), KeyModifiers::NONE)).is_empty());
+    }
+}
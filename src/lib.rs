@@ -1,4 +1,24 @@
 pub mod setup;
+pub mod config;
 pub mod engine;
+pub mod indexer;
+pub mod scorer;
+pub mod query;
 pub mod app;
+pub mod editor;
+pub mod history;
+pub mod frecency;
+pub mod keymap;
 pub mod viewer;
+pub mod highlight;
+pub mod theme;
+pub mod hexdump;
+pub mod encoding;
+pub mod text;
+pub mod preview;
+pub mod terminal;
+pub mod events;
+pub mod error;
+
+#[cfg(test)]
+mod fixture;
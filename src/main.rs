@@ -1,16 +1,80 @@
-use std::io;
+use std::{env, process::ExitCode};
 
-use fzf::setup::Setup;
+use fzf::setup::{self, Setup};
 use fzf::engine::Engine;
-use fzf::app::App;
+use fzf::app::{self, App};
+use fzf::events::{Events, Message};
+use fzf::frecency::{self, Frecency};
+use fzf::{indexer, terminal};
+
+fn main() -> ExitCode {
+    // maintenance of the frecency database, without the ui
+    if let Some(command) = env::args().find(|arg| arg == "--prune-frecency" || arg == "--clear-frecency") {
+        return frecency_command(&command);
+    }
 
-fn main() -> io::Result<()> {
     // get env ars
-    let setup = Setup::new();
-    let engine = Engine::new(setup);
+    let setup = match Setup::new() {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::from(2);
+        }
+    };
+    let events = Events::new(app::TICK_RATE);
+
+    if let Err(e) = indexer::spawn(&setup, events.sender()) {
+        eprintln!("error: {e}");
+        return ExitCode::from(2);
+    }
+    let frecency_path = setup.frecency.then(setup::data_dir).flatten().map(|dir| dir.join(frecency::DB_FILE));
+    let mut engine = Engine::new(setup);
+    match Frecency::load(frecency_path.as_deref()) {
+        Ok(frecency) => engine.set_frecency(frecency),
+        Err(e) => {
+            let _ = events.sender().send(Message::Error(e));
+        }
+    }
+
+    let mut app = App::new(engine, events);
+    let app_result = terminal::init().and_then(|mut terminal| app.run(&mut terminal));
+    terminal::restore();
+
+    if let Ok(Some(selected)) = &app_result
+        && let Err(e) = app.remember(selected) {
+        eprintln!("error: {e}");
+    }
+
+    match app_result {
+        Ok(Some(selected)) => {
+            for path in selected {
+                println!("{path}");
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(None) => ExitCode::from(130),
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn frecency_command(command: &str) -> ExitCode {
+    let path = setup::data_dir().map(|dir| dir.join(frecency::DB_FILE));
+    let result = Frecency::load(path.as_deref()).and_then(|mut frecency| match command {
+        "--clear-frecency" => frecency.clear().map(|_| "frecency database cleared".to_string()),
+        _ => frecency.prune().map(|pruned| format!("pruned {pruned} entries")),
+    });
 
-    let mut terminal = ratatui::init();
-    let app_result = App::new(engine).run(&mut terminal);
-    ratatui::restore();
-    app_result
+    match result {
+        Ok(message) => {
+            eprintln!("{message}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(2)
+        }
+    }
 }
@@ -0,0 +1,384 @@
+use std::{collections::VecDeque, fs::{self, FileType, Metadata}, io::{self, BufRead, BufReader}, os::unix::{fs::{FileTypeExt, PermissionsExt}, process::CommandExt}, path::Path, sync::{atomic::{AtomicU64, Ordering}, mpsc::{self, Receiver, RecvTimeoutError, Sender}, Arc}, process::{Command, Stdio}, thread, time::{Duration, Instant, SystemTime}};
+use crate::{engine::InputSource, error::{Error, Result}, events::Message, setup::Setup, viewer::Viewer};
+
+const CACHE_SIZE: usize = 16;
+const MAX_OUTPUT_LINES: usize = 10_000; // of a --preview command, it's killed after that
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(3); // e.g. tail -f, what it printed so far is shown
+const POLL_INTERVAL: Duration = Duration::from_millis(20); // how often a running command checks for a newer load
+
+pub enum Content {
+    File(Viewer),
+    Directory(Viewer), // sorted listing, directories first
+    Command(Viewer),   // output of the --preview command
+    Line,  // not a path (e.g. text piped on stdin), the line itself is shown
+    Empty, // files that can't be shown
+}
+
+impl Content {
+    pub fn viewer(&mut self) -> Option<&mut Viewer> {
+        match self {
+            Content::File(viewer) | Content::Directory(viewer) | Content::Command(viewer) => Some(viewer),
+            _ => None,
+        }
+    }
+}
+
+pub struct Preview {
+    pub path: String,
+    pub modified: Option<SystemTime>,
+    pub content: Content,
+}
+
+// what the worker needs to know from the setup
+struct Options {
+    walked: bool,
+    map_syntax: Vec<(String, String)>,
+    command: Option<String>,
+}
+
+enum Request {
+    Load { path: String, generation: u64 },
+    Store(Box<Preview>),
+}
+
+// loads previews on a worker thread, newer requests cancel older ones
+pub struct Previewer {
+    requests: Sender<Request>,
+    generation: Arc<AtomicU64>,
+}
+
+impl Previewer {
+    pub fn new(sender: Sender<Message>, setup: &Setup) -> Self {
+        // walked paths always exist, the ones read from stdin may be plain text
+        let (requests, receiver) = mpsc::channel();
+        let generation = Arc::new(AtomicU64::new(0));
+        let options = Options {
+            walked: setup.source == InputSource::Walk,
+            map_syntax: setup.map_syntax.clone(),
+            command: setup.preview.clone(),
+        };
+
+        let current = Arc::clone(&generation);
+        thread::spawn(move || Previewer::work(receiver, current, sender, options));
+
+        Previewer { requests, generation }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn load(&self, path: &str) {
+        // answered with Message::PreviewReady unless cancelled by a newer load
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let _ = self.requests.send(Request::Load { path: path.to_string(), generation });
+    }
+
+    pub fn store(&self, preview: Preview) {
+        // keeps a preview that is no longer shown (scroll position included) for later
+        let _ = self.requests.send(Request::Store(Box::new(preview)));
+    }
+
+    fn work(receiver: Receiver<Request>, current: Arc<AtomicU64>, sender: Sender<Message>, options: Options) {
+        // cache of the last previews, most recent first
+        let mut cache: VecDeque<Preview> = VecDeque::new();
+
+        while let Ok(request) = receiver.recv() {
+            let (path, generation) = match request {
+                Request::Store(preview) => {
+                    Previewer::cache(&mut cache, *preview);
+                    continue;
+                }
+                Request::Load { path, generation } => (path, generation),
+            };
+
+            if generation != current.load(Ordering::SeqCst) {
+                continue;
+            }
+
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            let cached = cache
+                .iter()
+                .position(|p| p.path == path && p.modified == modified)
+                .and_then(|i| cache.remove(i));
+
+            let preview = match cached {
+                Some(preview) => preview,
+                None => {
+                    // the error is shown in the status bar, the preview stays empty
+                    let cancelled = || generation != current.load(Ordering::SeqCst);
+                    let content = Previewer::read(&path, &options, &cancelled).unwrap_or_else(|e| {
+                        let _ = sender.send(Message::Error(e));
+                        Content::Empty
+                    });
+                    Preview { content, path, modified }
+                }
+            };
+
+            if generation != current.load(Ordering::SeqCst) {
+                Previewer::cache(&mut cache, preview);
+                continue;
+            }
+
+            if sender.send(Message::PreviewReady(generation, Box::new(preview))).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn cache(cache: &mut VecDeque<Preview>, preview: Preview) {
+        if !matches!(preview.content, Content::File(_) | Content::Directory(_)) {
+            return;
+        }
+
+        cache.retain(|p| p.path != preview.path);
+        cache.push_front(preview);
+        cache.truncate(CACHE_SIZE);
+    }
+
+    fn read(path: &str, options: &Options, cancelled: &dyn Fn() -> bool) -> Result<Content> {
+        if let Some(command) = &options.command {
+            let output = Previewer::run(command, path, cancelled).map_err(|e| Error::io(path, e))?;
+            return Ok(Content::Command(Viewer::from_output(output)));
+        }
+
+        let path_ref = Path::new(path);
+        if !options.walked && fs::symlink_metadata(path_ref).is_err() {
+            return Ok(Content::Line);
+        }
+
+        // a walked path that is gone by now fails below
+        if path_ref.is_dir() {
+            let listing = Previewer::list(path_ref).map_err(|e| Error::io(path, e))?;
+            return Ok(Content::Directory(Viewer::from_lines(listing)));
+        }
+
+        // binary files come back as a hex dump
+        match Viewer::new(path, &options.map_syntax) {
+            Ok(viewer) => Ok(Content::File(viewer)),
+            Err(e) => Err(Error::io(path, e)),
+        }
+    }
+
+    fn run(command: &str, path: &str, cancelled: &dyn Fn() -> bool) -> io::Result<Vec<String>> {
+        // sh -c with {} replaced by the quoted path, stderr is shown along with stdout
+        // killed once a newer preview is asked for, after COMMAND_TIMEOUT or MAX_OUTPUT_LINES
+        // in a process group of its own, so e.g. both sides of a pipe are killed with the shell
+        let quoted = format!("'{}'", path.replace('\'', "'\\''"));
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("exec 2>&1; {}", command.replace("{}", &quoted)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .process_group(0)
+            .spawn()?;
+
+        // read on a helper thread, so the worker can poll for cancellation meanwhile
+        let stdout = child.stdout.take().ok_or_else(|| io::Error::other("no stdout"))?;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).split(b'\n').map_while(|line| line.ok()) {
+                if sender.send(String::from_utf8_lossy(&line).trim_end_matches('\r').to_string()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let started = Instant::now();
+        let mut lines = Vec::new();
+        while lines.len() < MAX_OUTPUT_LINES && started.elapsed() < COMMAND_TIMEOUT && !cancelled() {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(line) => lines.push(line),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        // the group id is the pid of sh, once every member is gone the reader sees the end of the pipe
+        unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) };
+        let _ = child.wait();
+        Ok(lines)
+    }
+
+    fn list(path: &Path) -> io::Result<Vec<String>> {
+        // one line per entry: type and permission bits, size and name
+        let mut entries: Vec<(bool, String, String)> = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let Ok(meta) = entry.path().symlink_metadata() else {
+                    return (false, name.clone(), format!("?????????? {:>6}  {name}", "?"));
+                };
+
+                let is_dir = entry.path().is_dir();
+                let name = match meta.file_type().is_symlink() {
+                    true => match fs::read_link(entry.path()) {
+                        Ok(target) => format!("{name} -> {}", target.display()),
+                        Err(_) => name,
+                    },
+                    false if meta.is_dir() => format!("{name}/"),
+                    false => name,
+                };
+
+                let size = if meta.is_dir() {"-".to_string()} else {human_size(meta.len())};
+                (is_dir, name.clone(), format!("{} {size:>6}  {name}", mode_string(&meta)))
+            })
+            .collect();
+
+        entries.sort_by(|(a_dir, a, _), (b_dir, b, _)| b_dir.cmp(a_dir).then_with(|| a.cmp(b)));
+
+        match entries.is_empty() {
+            true => Ok(vec!["(empty directory)".to_string()]),
+            false => Ok(entries.into_iter().map(|(_, _, line)| line).collect()),
+        }
+    }
+}
+
+fn type_char(file_type: FileType) -> char {
+    match file_type {
+        t if t.is_dir() => 'd',
+        t if t.is_symlink() => 'l',
+        t if t.is_fifo() => 'p',
+        t if t.is_socket() => 's',
+        t if t.is_char_device() => 'c',
+        t if t.is_block_device() => 'b',
+        _ => '-',
+    }
+}
+
+fn mode_string(meta: &Metadata) -> String {
+    // like ls -l, e.g. drwxr-xr-x
+    let mode = meta.permissions().mode();
+    let mut out = String::from(type_char(meta.file_type()));
+
+    for (i, chr) in "rwxrwxrwx".chars().enumerate() {
+        out.push(if mode & (0o400 >> i) != 0 {chr} else {'-'});
+    }
+
+    out
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{bytes}{}", UNITS[0]),
+        _ => format!("{size:.1}{}", UNITS[unit]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TempPath;
+
+    fn previewer(flags: &[&str]) -> (Previewer, Receiver<Message>) {
+        let args: Vec<String> = ["prog", "."].iter().chain(flags).map(|a| a.to_string()).collect();
+        let (sender, receiver) = mpsc::channel();
+        (Previewer::new(sender, &Setup::from_args(&args, true).unwrap()), receiver)
+    }
+
+    fn next_ready(receiver: &Receiver<Message>, wait: Duration) -> Option<(u64, Preview)> {
+        // the next preview sent within wait, the first file also loads the syntaxes
+        while let Ok(message) = receiver.recv_timeout(wait) {
+            if let Message::PreviewReady(generation, preview) = message {
+                return Some((generation, *preview));
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(human_size(512), "512B");
+        assert_eq!(human_size(2048), "2.0K");
+        assert_eq!(human_size(5 * 1024 * 1024 + 1024 * 512), "5.5M");
+    }
+
+    #[test]
+    fn lists_directories_first() {
+        let dir = TempPath::dir("list");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.txt"), "hello").unwrap();
+
+        let listing = Previewer::list(&dir).unwrap();
+
+        assert_eq!(listing.len(), 2);
+        assert!(listing[0].starts_with('d') && listing[0].ends_with("sub/"));
+        assert!(listing[1].starts_with("-rw") && listing[1].ends_with("5B  a.txt"));
+    }
+
+    #[test]
+    fn runs_preview_commands() {
+        assert_eq!(Previewer::run("echo {}; echo oops >&2", "it's here", &|| false).unwrap(), vec!["it's here", "oops"]);
+    }
+
+    #[test]
+    fn kills_cancelled_commands() {
+        let started = Instant::now();
+        let cancelled = || started.elapsed() >= Duration::from_millis(200);
+
+        assert_eq!(Previewer::run("echo first; sleep 10; echo never", "", &cancelled).unwrap(), vec!["first"]);
+        assert!(started.elapsed() < COMMAND_TIMEOUT);
+    }
+
+    #[test]
+    fn kills_the_whole_command_pipeline() {
+        // the sleeps outlive sh, nothing of the pipe may touch the file afterwards
+        let late = TempPath::new("late");
+        let command = format!("echo first; (sleep 0.5; touch {}) | cat", late.display());
+
+        let started = Instant::now();
+        let cancelled = || started.elapsed() >= Duration::from_millis(100);
+        assert_eq!(Previewer::run(&command, "", &cancelled).unwrap(), vec!["first"]);
+
+        let timed_out = Previewer::run(&format!("sleep {}; {command}", COMMAND_TIMEOUT.as_secs_f32() - 0.2), "", &|| false).unwrap();
+        assert_eq!(timed_out, vec!["first"]);
+
+        thread::sleep(Duration::from_millis(800));
+        assert!(!late.exists());
+    }
+
+    #[test]
+    fn never_sends_superseded_previews() {
+        let (previewer, receiver) = previewer(&["--preview", "sleep 0.3; echo {}"]);
+        previewer.load("first");
+        thread::sleep(Duration::from_millis(50));
+        previewer.load("second");
+
+        let (generation, preview) = next_ready(&receiver, Duration::from_secs(10)).unwrap();
+        assert_eq!((generation, preview.path.as_str()), (2, "second"));
+        assert!(next_ready(&receiver, Duration::from_secs(1)).is_none());
+    }
+
+    #[test]
+    fn reuses_cached_previews_while_unmodified() {
+        let file = TempPath::file("cached", b"one\ntwo\n");
+        let path = file.to_str().unwrap();
+        let (previewer, receiver) = previewer(&[]);
+
+        // the scroll position tells a cached preview from a fresh one
+        previewer.load(path);
+        let (_, mut preview) = next_ready(&receiver, Duration::from_secs(10)).unwrap();
+        preview.content.viewer().unwrap().display_start = 1;
+        previewer.store(preview);
+
+        previewer.load(path);
+        let (_, mut preview) = next_ready(&receiver, Duration::from_secs(10)).unwrap();
+        assert_eq!(preview.content.viewer().unwrap().display_start, 1);
+        previewer.store(preview);
+
+        let modified = SystemTime::now() + Duration::from_secs(60);
+        fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+        previewer.load(path);
+        let (_, mut preview) = next_ready(&receiver, Duration::from_secs(10)).unwrap();
+        assert_eq!(preview.content.viewer().unwrap().display_start, 0);
+    }
+}
@@ -0,0 +1,290 @@
+use std::borrow::Cow;
+
+use crate::scorer::{self, Match};
+
+// fzf's extended search syntax:
+//   foo bar    both terms have to match
+//   'foo       exact match
+//   ^foo       prefix match
+//   foo$       suffix match
+//   ^foo$      whole string match
+//   !foo       inverse exact match (combines with ^ and $)
+//   foo | bar  either term matches
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CaseMode {
+    Smart,   // case sensitive only when the query has an uppercase char
+    Ignore,
+    Respect,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TermKind {
+    Fuzzy,
+    Exact,
+    Prefix,
+    Suffix,
+    Equal,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Term {
+    pub kind: TermKind,
+    pub inverse: bool,
+    pub text: Vec<char>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    pub groups: Vec<Vec<Term>>, // terms inside a group are OR-ed, groups are AND-ed
+    pub case_sensitive: bool,   // term texts are folded when false
+}
+
+impl Term {
+    fn parse(token: &str) -> Option<Term> {
+        let mut text = token;
+        let mut term = Term { kind: TermKind::Fuzzy, inverse: false, text: Vec::new() };
+
+        if let Some(rest) = text.strip_prefix('!') {
+            term.inverse = true;
+            term.kind = TermKind::Exact;
+            text = rest;
+        }
+
+        if let Some(rest) = text.strip_prefix('\'') {
+            term.kind = TermKind::Exact;
+            text = rest;
+        } else if let Some(rest) = text.strip_prefix('^') {
+            term.kind = TermKind::Prefix;
+            text = rest;
+        }
+
+        if let Some(rest) = text.strip_suffix('$').filter(|_| !text.ends_with("\\$")) {
+            term.kind = if term.kind == TermKind::Prefix {TermKind::Equal} else {TermKind::Suffix};
+            text = rest;
+        }
+
+        term.text = text.replace("\\$", "$").chars().collect();
+
+        // a lone operator (e.g. while it is still being typed) matches everything
+        (!term.text.is_empty()).then_some(term)
+    }
+
+    fn find(&self, text: &[char], case_sensitive: bool) -> Option<Match> {
+        match self.kind {
+            TermKind::Fuzzy => scorer::fuzzy_match(text, &self.text, case_sensitive),
+            TermKind::Exact => scorer::exact_match(text, &self.text, case_sensitive),
+            TermKind::Prefix => scorer::prefix_match(text, &self.text, case_sensitive),
+            TermKind::Suffix => scorer::suffix_match(text, &self.text, case_sensitive),
+            TermKind::Equal if text.len() == self.text.len() => scorer::prefix_match(text, &self.text, case_sensitive),
+            TermKind::Equal => None,
+        }
+    }
+
+    fn matches(&self, text: &[char], case_sensitive: bool) -> Option<Match> {
+        match (self.inverse, self.find(text, case_sensitive)) {
+            (false, found) => found,
+            (true, Some(_)) => None,
+            (true, None) => Some(Match { score: 0, positions: Vec::new() }),
+        }
+    }
+
+    fn implies(&self, other: &Term) -> bool {
+        // true when every string matched by self is also matched by other
+        match (self.inverse, other.inverse) {
+            (false, false) => Term::positive_implies(self, other),
+            (true, true) => Term::positive_implies(other, self),
+            _ => false,
+        }
+    }
+
+    fn positive_implies(a: &Term, b: &Term) -> bool {
+        use TermKind::*;
+
+        let contains = |hay: &[char], needle: &[char]| hay.windows(needle.len()).any(|w| w == needle);
+
+        match b.kind {
+            Fuzzy => {
+                let mut rest = a.text.iter();
+                b.text.iter().all(|c| rest.any(|x| x == c))
+            }
+            Exact => a.kind != Fuzzy && contains(&a.text, &b.text),
+            Prefix => matches!(a.kind, Prefix | Equal) && a.text.starts_with(&b.text),
+            Suffix => matches!(a.kind, Suffix | Equal) && a.text.ends_with(&b.text),
+            Equal => a.kind == Equal && a.text == b.text,
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(query: &str, case: CaseMode) -> Query {
+        let case_sensitive = match case {
+            CaseMode::Smart => query.chars().any(char::is_uppercase),
+            CaseMode::Ignore => false,
+            CaseMode::Respect => true,
+        };
+
+        let mut groups: Vec<Vec<Term>> = Vec::new();
+        let mut or_next = false;
+
+        // "\ " is a literal space inside a term
+        let escaped = query.replace("\\ ", "\u{0}");
+        for token in escaped.split(' ').filter(|t| !t.is_empty()) {
+            if token == "|" {
+                or_next = !groups.is_empty();
+                continue;
+            }
+
+            let Some(term) = Term::parse(&token.replace('\u{0}', " ")) else {
+                continue;
+            };
+
+            match groups.last_mut() {
+                Some(group) if or_next => group.push(term),
+                _ => groups.push(vec![term]),
+            }
+            or_next = false;
+        }
+
+        let query = Query { groups, case_sensitive: true };
+        match case_sensitive {
+            true => query,
+            false => query.folded(),
+        }
+    }
+
+    fn folded(&self) -> Query {
+        let mut query = self.clone();
+        for term in query.groups.iter_mut().flatten() {
+            term.text.iter_mut().for_each(|c| *c = scorer::fold_case(*c));
+        }
+        query.case_sensitive = false;
+
+        query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn matches(&self, text: &[char]) -> Option<Match> {
+        // score is the sum over groups, positions the union of matched terms
+        let mut result = Match { score: 0, positions: Vec::new() };
+
+        for group in &self.groups {
+            let found = group.iter().find_map(|term| term.matches(text, self.case_sensitive))?;
+            result.score += found.score;
+            result.positions.extend(found.positions);
+        }
+
+        result.positions.sort_unstable();
+        result.positions.dedup();
+        Some(result)
+    }
+
+    pub fn narrows(&self, other: &Query) -> bool {
+        // true when results of self are a subset of results of other,
+        // so they can be computed by filtering other's results
+        if other.is_empty() {
+            return true;
+        }
+
+        // case sensitive matches are a subset of insensitive ones, not the other way around,
+        // inverse terms flip that so they are dropped (which only widens self)
+        let this = match (self.case_sensitive, other.case_sensitive) {
+            (false, true) => return false,
+            (true, false) => {
+                let mut folded = self.folded();
+                folded.groups.retain(|group| group.iter().all(|term| !term.inverse));
+                Cow::Owned(folded)
+            }
+            _ => Cow::Borrowed(self),
+        };
+
+        other.groups.iter().all(|other_group| {
+            this.groups.iter().any(|group| {
+                group.iter().all(|term| other_group.iter().any(|other_term| term.implies(other_term)))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(kind: TermKind, inverse: bool, text: &str) -> Term {
+        Term { kind, inverse, text: text.chars().collect() }
+    }
+
+    fn matches(query: &str, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        Query::parse(query, CaseMode::Smart).matches(&text).is_some()
+    }
+
+    #[test]
+    fn parses_term_kinds() {
+        let query = Query::parse("foo 'bar ^src .rs$ !test ^main.rs$ !^tmp", CaseMode::Respect);
+
+        assert_eq!(query.groups, vec![
+            vec![term(TermKind::Fuzzy, false, "foo")],
+            vec![term(TermKind::Exact, false, "bar")],
+            vec![term(TermKind::Prefix, false, "src")],
+            vec![term(TermKind::Suffix, false, ".rs")],
+            vec![term(TermKind::Exact, true, "test")],
+            vec![term(TermKind::Equal, false, "main.rs")],
+            vec![term(TermKind::Prefix, true, "tmp")],
+        ]);
+    }
+
+    #[test]
+    fn parses_or_groups_and_lone_operators() {
+        let query = Query::parse("^src | ^lib  rs$ ! ' |", CaseMode::Respect);
+
+        assert_eq!(query.groups, vec![
+            vec![term(TermKind::Prefix, false, "src"), term(TermKind::Prefix, false, "lib")],
+            vec![term(TermKind::Suffix, false, "rs")],
+        ]);
+        assert!(Query::parse("  ", CaseMode::Respect).is_empty());
+    }
+
+    #[test]
+    fn evaluates_terms() {
+        assert!(matches("src rs$", "src/main.rs"));
+        assert!(!matches("src rs$", "src/main.rs.bak"));
+        assert!(matches("'ain !test", "src/main.rs"));
+        assert!(!matches("'ain !test", "tests/main_test.rs"));
+        assert!(matches("^doc | ^src", "src/main.rs"));
+        assert!(!matches("^doc | ^lib", "src/main.rs"));
+        assert!(matches("my\\ file", "a/my file.txt"));
+    }
+
+    #[test]
+    fn detects_narrowing() {
+        let narrows = |new: &str, old: &str| Query::parse(new, CaseMode::Smart).narrows(&Query::parse(old, CaseMode::Smart));
+
+        assert!(narrows("foo", ""));
+        assert!(narrows("foob", "foo"));
+        assert!(narrows("foo b", "foo "));
+        assert!(narrows("foo bar$", "foo bar"));
+        assert!(narrows("!foo", "!fooo"));
+        assert!(!narrows("!fooo", "!foo"));
+        assert!(!narrows("foo | bar", "foo"));
+        assert!(!narrows("^foo", "'oof"));
+        assert!(narrows("fooB", "foo"));
+        assert!(!narrows("foo", "Foo"));
+        assert!(!narrows("foo !Bar", "foo !bar"));
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(matches("readme", "./README.md"));
+        assert!(matches("README", "./README.md"));
+        assert!(!matches("ReadMe", "./README.md"));
+        assert!(matches("żółw", "./ŻÓŁW.txt"));
+
+        let text: Vec<char> = "./README.md".chars().collect();
+        assert!(Query::parse("readme", CaseMode::Respect).matches(&text).is_none());
+        assert!(Query::parse("ReadMe", CaseMode::Ignore).matches(&text).is_some());
+    }
+}
@@ -0,0 +1,320 @@
+use std::borrow::Cow;
+
+// fuzzy scoring in the spirit of fzf's v2 algorithm: a Smith-Waterman style
+// alignment of the pattern over the text, rewarding matches on word
+// boundaries and consecutive runs, penalizing gaps between matched chars
+
+pub const SCORE_MATCH: i32 = 16;
+const SCORE_GAP_START: i32 = -3;
+const SCORE_GAP_EXTENSION: i32 = -1;
+
+const BONUS_BOUNDARY: i32 = SCORE_MATCH / 2;
+const BONUS_BOUNDARY_WHITE: i32 = BONUS_BOUNDARY + 2;
+const BONUS_BOUNDARY_DELIMITER: i32 = BONUS_BOUNDARY + 1;
+const BONUS_NON_WORD: i32 = SCORE_MATCH / 2;
+const BONUS_CAMEL_123: i32 = BONUS_BOUNDARY + SCORE_GAP_EXTENSION;
+const BONUS_CONSECUTIVE: i32 = -(SCORE_GAP_START + SCORE_GAP_EXTENSION);
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+const BONUS_BASENAME: i32 = 2;
+
+const NONE: i32 = i32::MIN / 2;
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum CharClass {
+    White,
+    NonWord,
+    Delimiter,
+    Lower,
+    Upper,
+    Number,
+}
+
+pub struct Match {
+    pub score: i32,
+    pub positions: Vec<usize>, // char indices into the text
+}
+
+fn char_class(c: char) -> CharClass {
+    match c {
+        c if c.is_whitespace() => CharClass::White,
+        '/' | '\\' | ',' | ':' | ';' | '|' => CharClass::Delimiter,
+        c if c.is_lowercase() => CharClass::Lower,
+        c if c.is_uppercase() => CharClass::Upper,
+        c if c.is_numeric() => CharClass::Number,
+        c if c.is_alphabetic() => CharClass::Lower,
+        _ => CharClass::NonWord,
+    }
+}
+
+fn bonus_for(prev: CharClass, curr: CharClass) -> i32 {
+    if curr > CharClass::Delimiter {
+        match prev {
+            CharClass::White => return BONUS_BOUNDARY_WHITE,
+            CharClass::Delimiter => return BONUS_BOUNDARY_DELIMITER,
+            CharClass::NonWord => return BONUS_BOUNDARY,
+            _ => {}
+        }
+    }
+
+    match (prev, curr) {
+        (CharClass::Lower, CharClass::Upper) => BONUS_CAMEL_123,
+        (p, CharClass::Number) if p != CharClass::Number => BONUS_CAMEL_123,
+        (_, CharClass::NonWord | CharClass::Delimiter) => BONUS_NON_WORD,
+        (_, CharClass::White) => BONUS_BOUNDARY_WHITE,
+        _ => 0,
+    }
+}
+
+fn bonuses(text: &[char]) -> Vec<i32> {
+    // the start of the text counts as a path boundary
+    let mut prev = CharClass::Delimiter;
+    let basename_start = text.iter().rposition(|&c| c == '/').map_or(0, |i| i + 1);
+
+    text.iter()
+        .enumerate()
+        .map(|(i, &c)| {
+            let curr = char_class(c);
+            let bonus = bonus_for(prev, curr) + if i >= basename_start {BONUS_BASENAME} else {0};
+            prev = curr;
+            bonus
+        })
+        .collect()
+}
+
+pub fn fold_case(c: char) -> char {
+    // simple unicode case folding, keeps one char per char so positions stay valid
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn fold_text(text: &[char], case_sensitive: bool) -> Cow<'_, [char]> {
+    match case_sensitive {
+        true => Cow::Borrowed(text),
+        false => Cow::Owned(text.iter().map(|&c| fold_case(c)).collect()),
+    }
+}
+
+// pattern is expected to be folded already when matching case insensitively,
+// text is kept as is since its case drives the camelCase bonus
+pub fn fuzzy_match(text: &[char], pattern: &[char], case_sensitive: bool) -> Option<Match> {
+    let (n, m) = (text.len(), pattern.len());
+    if m == 0 {
+        return Some(Match { score: 0, positions: Vec::new() });
+    }
+
+    let bonus_text = text;
+    let text = &*fold_text(text, case_sensitive);
+
+    // cheap greedy subsequence check before the full alignment
+    let mut rest = text.iter();
+    if !pattern.iter().all(|p| rest.any(|c| c == p)) {
+        return None;
+    }
+
+    // narrow the window to [first occurrence of pattern[0], last occurrence of pattern[m - 1]]
+    let first = text.iter().position(|&c| c == pattern[0])?;
+    let last = text.iter().rposition(|&c| c == pattern[m - 1])?;
+    if last < first {
+        return None;
+    }
+
+    let bonus = bonuses(bonus_text);
+
+    // best[i][j]: best score with pattern[i] matched at text[j]
+    // gap[j]: best score with the previous pattern char matched before j and text up to j skipped
+    // run[i][j]: length of the consecutive run ending at (i, j) on the best path
+    // from[i][j]: where pattern[i - 1] was matched on the best path
+    let mut best = vec![NONE; m * n];
+    let mut run = vec![0usize; m * n];
+    let mut from = vec![0usize; m * n];
+    let mut gap = vec![NONE; n];
+    let mut gap_from = vec![0usize; n];
+
+    for (i, &p) in pattern.iter().enumerate() {
+        let row = i * n;
+        let prev_row = row.wrapping_sub(n);
+
+        for j in first + i..=last {
+            if text[j] != p {
+                continue;
+            }
+
+            if i == 0 {
+                best[j] = SCORE_MATCH + bonus[j] * BONUS_FIRST_CHAR_MULTIPLIER;
+                run[j] = 1;
+                continue;
+            }
+
+            // extend a consecutive run
+            let mut candidate = NONE;
+            if best[prev_row + j - 1] > NONE {
+                let len = run[prev_row + j - 1] + 1;
+                let run_bonus = bonus[j + 1 - len].max(BONUS_CONSECUTIVE);
+                let b = if bonus[j] >= BONUS_BOUNDARY && bonus[j] > run_bonus {bonus[j]} else {bonus[j].max(run_bonus)};
+                candidate = best[prev_row + j - 1] + SCORE_MATCH + b;
+                run[row + j] = len;
+                from[row + j] = j - 1;
+            }
+
+            // or jump over a gap
+            if gap[j - 1] > NONE {
+                let jumped = gap[j - 1] + SCORE_MATCH + bonus[j];
+                if jumped > candidate {
+                    candidate = jumped;
+                    run[row + j] = 1;
+                    from[row + j] = gap_from[j - 1];
+                }
+            }
+
+            best[row + j] = candidate;
+        }
+
+        // gap[j] for this row, consumed by the next one
+        gap.fill(NONE);
+        for j in first + i + 1..=last {
+            let open = best[row + j - 1] + SCORE_GAP_START;
+            let extend = gap[j - 1] + SCORE_GAP_EXTENSION;
+            if best[row + j - 1] > NONE && open >= extend {
+                gap[j] = open;
+                gap_from[j] = j - 1;
+            } else if gap[j - 1] > NONE {
+                gap[j] = extend;
+                gap_from[j] = gap_from[j - 1];
+            }
+        }
+    }
+
+    // pick the best end position and walk back
+    let row = (m - 1) * n;
+    let (mut j, score) = (first..=last)
+        .map(|j| (j, best[row + j]))
+        .filter(|&(_, s)| s > NONE)
+        .max_by_key(|&(j, s)| (s, usize::MAX - j))?;
+
+    let mut positions = vec![0; m];
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = from[i * n + j];
+    }
+
+    Some(Match { score, positions })
+}
+
+fn run_score(bonus: &[i32], start: usize, len: usize) -> i32 {
+    // score of pattern matched as one consecutive run text[start..start + len]
+    let mut score = 0;
+    let mut run_bonus = bonus[start];
+
+    for (i, &b) in bonus[start..start + len].iter().enumerate() {
+        score += SCORE_MATCH + match i {
+            0 => b * BONUS_FIRST_CHAR_MULTIPLIER,
+            _ if b >= BONUS_BOUNDARY && b > run_bonus => {
+                run_bonus = b;
+                b
+            }
+            _ => b.max(run_bonus).max(BONUS_CONSECUTIVE),
+        };
+    }
+
+    score
+}
+
+fn run_match(text: &[char], pattern: &[char], case_sensitive: bool, starts: impl Iterator<Item = usize>) -> Option<Match> {
+    // best scoring occurrence of pattern starting at one of starts
+    let bonus = bonuses(text);
+    let text = &*fold_text(text, case_sensitive);
+
+    starts
+        .filter(|&s| s + pattern.len() <= text.len() && text[s..s + pattern.len()] == *pattern)
+        .map(|s| (run_score(&bonus, s, pattern.len()), s))
+        .max_by_key(|&(score, s)| (score, usize::MAX - s))
+        .map(|(score, s)| Match { score, positions: (s..s + pattern.len()).collect() })
+}
+
+pub fn exact_match(text: &[char], pattern: &[char], case_sensitive: bool) -> Option<Match> {
+    if pattern.is_empty() {
+        return Some(Match { score: 0, positions: Vec::new() });
+    }
+    run_match(text, pattern, case_sensitive, 0..text.len())
+}
+
+pub fn prefix_match(text: &[char], pattern: &[char], case_sensitive: bool) -> Option<Match> {
+    if pattern.is_empty() {
+        return Some(Match { score: 0, positions: Vec::new() });
+    }
+    run_match(text, pattern, case_sensitive, 0..1)
+}
+
+pub fn suffix_match(text: &[char], pattern: &[char], case_sensitive: bool) -> Option<Match> {
+    if pattern.is_empty() {
+        return Some(Match { score: 0, positions: Vec::new() });
+    }
+    run_match(text, pattern, case_sensitive, text.len().checked_sub(pattern.len()).into_iter())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(text: &str, pattern: &str) -> Option<i32> {
+        let text: Vec<char> = text.chars().collect();
+        let pattern: Vec<char> = pattern.chars().collect();
+        fuzzy_match(&text, &pattern, true).map(|m| m.score)
+    }
+
+    fn positions(text: &str, pattern: &str) -> Vec<usize> {
+        let text: Vec<char> = text.chars().collect();
+        let pattern: Vec<char> = pattern.chars().collect();
+        fuzzy_match(&text, &pattern, true).unwrap().positions
+    }
+
+    #[test]
+    fn rejects_missing_subsequence() {
+        assert_eq!(score("src/main.rs", "mainx"), None);
+        assert_eq!(score("src/main.rs", "nm"), None);
+    }
+
+    #[test]
+    fn prefers_basename_over_scattered_match() {
+        assert!(score("./src/main.rs", "main") > score("./my_app/index.rs", "main"));
+        assert!(score("./src/main.rs", "main") > score("./m/a/i/n.rs", "main"));
+    }
+
+    #[test]
+    fn rewards_boundaries_and_camel_case() {
+        assert!(score("FooBar", "fb").is_none());
+        assert!(score("foo_bar", "fb") > score("foobar", "fb"));
+        assert!(score("fooBar", "fB") > score("foobBr", "fB"));
+    }
+
+    #[test]
+    fn finds_best_alignment() {
+        // the greedy leftmost match would pick the first 'm'
+        assert_eq!(positions("./my/src/main.rs", "main"), vec![9, 10, 11, 12]);
+        assert_eq!(positions("abc", "abc"), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn exact_match_picks_best_occurrence() {
+        let text: Vec<char> = "./domain/main.rs".chars().collect();
+        let pattern: Vec<char> = "main".chars().collect();
+
+        assert_eq!(exact_match(&text, &pattern, true).unwrap().positions, vec![9, 10, 11, 12]);
+        assert!(prefix_match(&text, &pattern, true).is_none());
+        let extension: Vec<char> = ".rs".chars().collect();
+        assert!(suffix_match(&text, &extension, true).is_some());
+    }
+
+    #[test]
+    fn folds_unicode_case() {
+        let text: Vec<char> = "./ŻÓŁW/Readme.MD".chars().collect();
+        let pattern: Vec<char> = "żółwreadme".chars().collect();
+
+        assert!(fuzzy_match(&text, &pattern, true).is_none());
+        assert!(fuzzy_match(&text, &pattern, false).is_some());
+    }
+
+    #[test]
+    fn positions_are_char_indices() {
+        assert_eq!(positions("zażółć/gęś.txt", "gś"), vec![7, 9]);
+    }
+}
@@ -1,46 +1,194 @@
-use std::{env, str::FromStr, path::Path};
+use std::{env, io::{self, IsTerminal}, str::FromStr, path::{Path, PathBuf}};
+use syntect::highlighting::Theme;
+use crate::{config, engine::InputSource, error::{Error, Result}, highlight, history, keymap::Keymap, query::CaseMode, theme::{self, Colors}};
+
+// flags that consume the next argument
+const FLAGS_WITH_VAL: &[&str] = &["-d", "--exclude", "--map-syntax", "--theme", "--color", "--layout", "--preview", "--bind", "--history", "--history-size"];
 
 #[derive(Debug)]
 pub struct Setup {
     pub root_dir: String,
     pub deep: u8,
+    pub source: InputSource,
+    pub case: CaseMode,
+    pub hidden: bool,         // walk hidden files too
+    pub no_ignore: bool,      // don't honor .gitignore and friends
+    pub exclude: Vec<String>, // globs skipped by the walker
+    pub map_syntax: Vec<(String, String)>, // file name or extension and the syntax used for it
+    pub colors: Colors,
+    pub theme: &'static Theme, // for syntax highlighting
+    pub layout: LayoutMode,
+    pub preview: Option<String>, // shell command shown instead of the file, {} is the path
+    pub keymap: Keymap,
+    pub history: Option<PathBuf>, // file the accepted queries are kept in
+    pub history_size: usize,
+    pub frecency: bool, // boost paths that were accepted often and recently
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutMode {
+    Default, // input at the bottom, best match right above it
+    Reverse, // input at the top, matches listed downwards
 }
 
 impl Setup {
-    pub fn new() -> Self {
-        let args: Vec<String> = env::args().collect();
-        Setup::from_args(&args)
+    pub fn new() -> Result<Self> {
+        // config file < $FZF_RS_DEFAULT_OPTS < command line, for flags given more than once the last one wins
+        let mut args: Vec<String> = env::args().take(1).collect();
+        args.extend(config::default_args()?);
+        args.extend(env::args().skip(1));
+        Setup::from_args(&args, io::stdin().is_terminal())
     }
 
-    fn from_args(args: &[String]) -> Self {
+    pub(crate) fn from_args(args: &[String], stdin_is_tty: bool) -> Result<Self> {
         let mut setup = Setup {
             root_dir: ".".to_string(),
             deep: u8::MAX,
+            source: InputSource::Walk,
+            case: CaseMode::Smart,
+            hidden: false,
+            no_ignore: false,
+            exclude: Vec::new(),
+            map_syntax: Vec::new(),
+            colors: Colors::default(),
+            theme: Colors::default().default_theme(),
+            layout: LayoutMode::Default,
+            preview: None,
+            keymap: Keymap::default(),
+            history: None,
+            history_size: history::DEFAULT_SIZE,
+            frecency: true,
         };
     
-        // setup root_dir 
-        if args.len() < 2 {
-            panic!("error: you need to add root dir");
+        // setup root_dir, without it candidates are read from piped stdin
+        match Setup::positional_arg(args) {
+            Some(root_dir) => {
+                setup.root_dir = root_dir.to_string();
+                Setup::appropriate_root_path(&setup.root_dir)?;
+            }
+            None if !stdin_is_tty => {
+                let delimiter = match Setup::contains_flag_without_val(args, "--read0") {
+                    Some(()) => b'\0',
+                    None => b'\n',
+                };
+                setup.source = InputSource::Stdin { delimiter };
+            }
+            None => return Err(Error::Config("you need to add root dir".to_string())),
         }
-        setup.root_dir = args[1].to_string();
-        Setup::appropriate_root_path(&setup.root_dir).expect("error: innapropriate root path");
 
         // setup -d
-        if let Some(deep) = Setup::contains_flag_with_val(&args, "-d") {
-            setup.deep = deep;
+        // the root dir's own entries are at depth 1, -d 0 would list nothing
+        match Setup::contains_flag_with_val(args, "-d")? {
+            Some(0) => return Err(Error::Config("invalid value 0 for -d, the least depth is 1".to_string())),
+            Some(deep) => setup.deep = deep,
+            None => {}
+        }
+
+        // setup walker flags
+        setup.hidden = Setup::last_switch(args, "--hidden", "--no-hidden").unwrap_or(false);
+        setup.no_ignore = Setup::last_switch(args, "--no-ignore", "--ignore").unwrap_or(false);
+        setup.exclude = Setup::flag_values(args, "--exclude");
+
+        // setup --map-syntax ext:Syntax
+        setup.map_syntax = Setup::flag_values(args, "--map-syntax")
+            .into_iter()
+            .map(|mapping| {
+                let (key, syntax) = mapping.split_once(':').ok_or_else(|| Error::Config(format!("--map-syntax expects ext:Syntax, got {mapping}")))?;
+                if highlight::SYNTAX_SET.find_syntax_by_token(syntax).is_none() {
+                    return Err(Error::Config(format!("unknown syntax {syntax}")));
+                }
+                Ok((key.to_string(), syntax.to_string()))
+            })
+            .collect::<Result<_>>()?;
+
+        // setup --color, every one is applied in order
+        for spec in Setup::flag_values(args, "--color") {
+            setup.colors.apply(&spec).map_err(Error::Config)?;
+        }
+
+        // setup --theme, defaults to one matching the colors
+        setup.theme = match Setup::contains_flag_with_val::<String>(args, "--theme")? {
+            Some(name) => theme::load_theme(&name, config_dir().as_deref()).ok_or_else(|| Error::Config(format!("unknown theme {name}")))?,
+            None => setup.colors.default_theme(),
+        };
+
+        // setup --layout
+        setup.layout = match Setup::contains_flag_with_val::<String>(args, "--layout")?.as_deref() {
+            None | Some("default") => LayoutMode::Default,
+            Some("reverse") => LayoutMode::Reverse,
+            Some(layout) => return Err(Error::Config(format!("unknown layout {layout}"))),
+        };
+
+        // an empty --preview turns off the one from the defaults
+        setup.preview = Setup::contains_flag_with_val::<String>(args, "--preview")?.filter(|command| !command.is_empty());
+
+        // setup --bind key:action[,key:action], on top of the default keymap
+        for spec in Setup::flag_values(args, "--bind") {
+            setup.keymap.bind(&spec).map_err(Error::Config)?;
+        }
+
+        // setup --history and --history-size
+        setup.history = Setup::contains_flag_with_val::<PathBuf>(args, "--history")?;
+        if let Some(size) = Setup::contains_flag_with_val(args, "--history-size")? {
+            setup.history_size = size;
         }
 
-        setup
+        // setup --frecency / --no-frecency, the last one wins
+        setup.frecency = Setup::last_switch(args, "--frecency", "--no-frecency").unwrap_or(true);
+
+        // setup --ignore-case / --no-ignore-case, the last one wins
+        setup.case = args.iter().rev().find_map(|arg| match arg.as_str() {
+            "--ignore-case" => Some(CaseMode::Ignore),
+            "--no-ignore-case" => Some(CaseMode::Respect),
+            _ => None,
+        }).unwrap_or(CaseMode::Smart);
+
+        Ok(setup)
     }
     
-    fn contains_flag_with_val<T: FromStr>(args: &[String], flag: &str) -> Option<T> {
-        if let Some(pos) = args.iter().position(|x| x == flag) {
-            if let Some(val) = args.get(pos + 1) {
-                return val.parse::<T>().ok();
+    fn contains_flag_with_val<T: FromStr>(args: &[String], flag: &str) -> Result<Option<T>> {
+        // the last occurrence wins, so the command line overrides the defaults
+        if let Some(pos) = args.iter().rposition(|x| x == flag)
+            && let Some(val) = args.get(pos + 1) {
+            return val.parse::<T>().map(Some).map_err(|_| Error::Config(format!("invalid value {val} for {flag}")));
+        }
+
+        Ok(None)
+    }
+
+    fn last_switch(args: &[String], on: &str, off: &str) -> Option<bool> {
+        // a flag and its negation, the last one wins
+        args.iter().rev().find_map(|arg| match arg {
+            arg if arg == on => Some(true),
+            arg if arg == off => Some(false),
+            _ => None,
+        })
+    }
+
+    fn flag_values(args: &[String], flag: &str) -> Vec<String> {
+        // values of a flag that can be repeated
+        args.windows(2)
+            .filter(|pair| pair[0] == flag)
+            .map(|pair| pair[1].clone())
+            .collect()
+    }
+
+    fn positional_arg(args: &[String]) -> Option<&str> {
+        // the last one, like any other flag
+        let mut i = 1;
+        let mut positional = None;
+        while let Some(arg) = args.get(i) {
+            if FLAGS_WITH_VAL.contains(&arg.as_str()) {
+                i += 2;
+            } else if arg.starts_with('-') {
+                i += 1;
+            } else {
+                positional = Some(arg.as_str());
+                i += 1;
             }
         }
 
-        None
+        positional
     }
 
     fn contains_flag_without_val(args: &[String], flag: &str) -> Option<()> {
@@ -50,14 +198,34 @@ impl Setup {
         }
     }
 
-    fn appropriate_root_path(root_path: &str) -> Result<(), ()> {
+    fn appropriate_root_path(root_path: &str) -> Result<()> {
         Path::new(root_path)
             .is_dir()
             .then_some(())
-            .ok_or_else(|| ())
+            .ok_or_else(|| Error::Config("innapropriate root path".to_string()))
     }
 }
 
+pub fn config_dir() -> Option<PathBuf> {
+    // $XDG_CONFIG_HOME/fzf-rs, falling back to ~/.config/fzf-rs
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+    Some(base.join("fzf-rs"))
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    // $XDG_DATA_HOME/fzf-rs, falling back to ~/.local/share/fzf-rs
+    let base = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+
+    Some(base.join("fzf-rs"))
+}
+
 #[cfg(test)]
 mod tests {
     use std::vec;
@@ -65,23 +233,21 @@ mod tests {
     use super::*;
 
     #[test]
-    #[should_panic(expected = "error: you need to add root dir")]
     fn root_dir_absence() {
         let args = vec![
             "prog".into()
         ];
-        Setup::from_args(&args);
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "you need to add root dir");
     }
     
     #[test]
-    #[should_panic(expected = "error: innapropriate root path")]
     fn innapropriate_root_path() {
         let args = vec![
             "prog".into(),
             "innapropriate_path".into(),
         ];
 
-        Setup::from_args(&args);
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "innapropriate root path");
     }
 
     #[test]
@@ -93,7 +259,7 @@ mod tests {
             "7".into(),
         ];
 
-        assert_eq!(Setup::contains_flag_with_val(&args, "-d"), Some(7));
+        assert_eq!(Setup::contains_flag_with_val(&args, "-d").unwrap(), Some(7));
     }
     
     #[test]
@@ -104,7 +270,171 @@ mod tests {
             "-d".into(),
         ];
 
-        assert_eq!(Setup::contains_flag_with_val::<u8>(&args, "-d"), None);
+        assert_eq!(Setup::contains_flag_with_val::<u8>(&args, "-d").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_numbers() {
+        let args = vec!["prog".into(), ".".into(), "-d".into(), "deep".into()];
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "invalid value deep for -d");
+
+        let args = vec!["prog".into(), ".".into(), "-d".into(), "0".into()];
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "invalid value 0 for -d, the least depth is 1");
+
+        let args = vec!["prog".into(), ".".into(), "--history-size".into(), "-5".into()];
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "invalid value -5 for --history-size");
+    }
+
+    #[test]
+    fn reads_piped_stdin_without_root_dir() {
+        let args = vec![
+            "prog".into(),
+            "-d".into(),
+            "7".into(),
+        ];
+
+        let setup = Setup::from_args(&args, false).unwrap();
+        assert_eq!(setup.source, InputSource::Stdin { delimiter: b'\n' });
+        assert_eq!(setup.deep, 7);
+    }
+
+    #[test]
+    fn reads_nul_separated_stdin() {
+        let args = vec![
+            "prog".into(),
+            "--read0".into(),
+        ];
+
+        assert_eq!(Setup::from_args(&args, false).unwrap().source, InputSource::Stdin { delimiter: b'\0' });
+    }
+
+    #[test]
+    fn parses_case_flags() {
+        let args = |flags: &[&str]| {
+            let mut args: Vec<String> = vec!["prog".into(), ".".into()];
+            args.extend(flags.iter().map(|f| f.to_string()));
+            args
+        };
+
+        assert_eq!(Setup::from_args(&args(&[]), true).unwrap().case, CaseMode::Smart);
+        assert_eq!(Setup::from_args(&args(&["--ignore-case"]), true).unwrap().case, CaseMode::Ignore);
+        assert_eq!(Setup::from_args(&args(&["--ignore-case", "--no-ignore-case"]), true).unwrap().case, CaseMode::Respect);
+    }
+
+    #[test]
+    fn collects_repeated_excludes() {
+        let args = vec![
+            "prog".into(),
+            "--exclude".into(),
+            "target".into(),
+            ".".into(),
+            "--hidden".into(),
+            "--exclude".into(),
+            "*.lock".into(),
+        ];
+
+        let setup = Setup::from_args(&args, true).unwrap();
+        assert_eq!(setup.root_dir, ".");
+        assert_eq!(setup.exclude, vec!["target".to_string(), "*.lock".to_string()]);
+        assert!(setup.hidden);
+        assert!(!setup.no_ignore);
+    }
+
+    #[test]
+    fn negations_undo_walker_flags() {
+        let args: Vec<String> = ["prog", "--hidden", "--no-ignore", ".", "--no-hidden", "--ignore"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+
+        let setup = Setup::from_args(&args, true).unwrap();
+        assert!(!setup.hidden);
+        assert!(!setup.no_ignore);
+        assert!(Setup::from_args(&args[..4], true).unwrap().no_ignore);
+    }
+
+    #[test]
+    fn root_dir_wins_over_piped_stdin() {
+        let args = vec![
+            "prog".into(),
+            "-d".into(),
+            "2".into(),
+            ".".into(),
+        ];
+
+        let setup = Setup::from_args(&args, false).unwrap();
+        assert_eq!(setup.source, InputSource::Walk);
+        assert_eq!(setup.root_dir, ".");
     }
 
+    #[test]
+    fn collects_syntax_mappings() {
+        let args = vec![
+            "prog".into(),
+            "--map-syntax".into(),
+            "conf:YAML".into(),
+            ".".into(),
+        ];
+
+        let setup = Setup::from_args(&args, true).unwrap();
+        assert_eq!(setup.root_dir, ".");
+        assert_eq!(setup.map_syntax, vec![("conf".to_string(), "YAML".to_string())]);
+    }
+
+    #[test]
+    fn rejects_unknown_syntax() {
+        let args = vec!["prog".into(), "--map-syntax".into(), "kl:Klingon".into(), ".".into()];
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "unknown syntax Klingon");
+
+        let args = vec!["prog".into(), "--map-syntax".into(), "Klingon".into(), ".".into()];
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "--map-syntax expects ext:Syntax, got Klingon");
+    }
+
+    #[test]
+    fn theme_follows_color_scheme() {
+        let args = |flags: &[&str]| {
+            let mut args: Vec<String> = vec!["prog".into(), ".".into()];
+            args.extend(flags.iter().map(|f| f.to_string()));
+            args
+        };
+
+        let setup = Setup::from_args(&args(&["--color", "light", "--color", "match:cyan"]), true).unwrap();
+        assert_eq!(setup.colors.fg, ratatui::style::Color::Black);
+        assert_eq!(setup.colors.matched, ratatui::style::Color::Cyan);
+        assert_eq!(setup.theme.name.as_deref(), Some("GitHub"));
+
+        let setup = Setup::from_args(&args(&["--color", "light", "--theme", "Solarized (dark)"]), true).unwrap();
+        assert_eq!(setup.theme.name.as_deref(), Some("Solarized (dark)"));
+    }
+
+    #[test]
+    fn rejects_unknown_theme() {
+        let args = vec!["prog".into(), "--theme".into(), "nope".into(), ".".into()];
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "unknown theme nope");
+
+        let args = vec!["prog".into(), "--color".into(), "match:nope".into(), ".".into()];
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "invalid color nope");
+    }
+
+    #[test]
+    fn last_flag_wins() {
+        let args: Vec<String> = ["prog", "-d", "2", "--layout", "reverse", "--preview", "cat {}", ".", "-d", "5", "--layout", "default"]
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+
+        let setup = Setup::from_args(&args, true).unwrap();
+        assert_eq!(setup.deep, 5);
+        assert_eq!(setup.layout, LayoutMode::Default);
+        assert_eq!(setup.preview.as_deref(), Some("cat {}"));
+
+        let args = vec!["prog".into(), "--layout".into(), "sideways".into(), ".".into()];
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "unknown layout sideways");
+    }
+
+    #[test]
+    fn rejects_unknown_actions() {
+        let args = vec!["prog".into(), "--bind".into(), "ctrl-j:down,ctrl-k:jump".into(), ".".into()];
+        assert_eq!(Setup::from_args(&args, true).unwrap_err().to_string(), "unknown action jump");
+    }
 }
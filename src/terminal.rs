@@ -0,0 +1,42 @@
+use std::{fs::{File, OpenOptions}, io, panic, process};
+use crossterm::{event::{DisableBracketedPaste, EnableBracketedPaste}, execute, terminal::{self, EnterAlternateScreen, LeaveAlternateScreen}};
+use ratatui::{backend::CrosstermBackend, Terminal};
+use crate::error::{Error, Result};
+
+// the ui is drawn on the controlling terminal, so stdout stays free for the selection
+pub type Tui = Terminal<CrosstermBackend<File>>;
+
+fn open_tty() -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).open("/dev/tty")
+}
+
+pub fn init() -> Result<Tui> {
+    set_panic_hook();
+    enter().map_err(Error::Terminal)
+}
+
+fn enter() -> io::Result<Tui> {
+    terminal::enable_raw_mode()?;
+    let mut tty = open_tty()?;
+    execute!(tty, EnterAlternateScreen, EnableBracketedPaste)?;
+
+    Terminal::new(CrosstermBackend::new(tty))
+}
+
+pub fn restore() {
+    // best effort, we may be called from the panic hook
+    let _ = terminal::disable_raw_mode();
+    if let Ok(mut tty) = open_tty() {
+        let _ = execute!(tty, DisableBracketedPaste, LeaveAlternateScreen);
+    }
+}
+
+fn set_panic_hook() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // a panic on any thread leaves the ui unusable, so the whole program goes down
+        restore();
+        hook(info);
+        process::exit(101);
+    }));
+}
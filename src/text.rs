@@ -0,0 +1,198 @@
+use std::{fs::File, io, os::unix::fs::FileExt, sync::{Arc, Mutex, Weak}, thread};
+use crate::{encoding::{self, Encoding, REPLACEMENT}, hexdump::Search};
+
+const INDEX_CHUNK: u64 = 1024 * 1024;
+const MAX_LINE: u64 = 64 * 1024; // longer lines are cut when shown
+
+// a text file read on demand, only the lines on screen are decoded
+pub struct LazyText {
+    file: File,
+    len: u64,
+    encoding: Encoding,
+    index: Arc<Mutex<Index>>,
+}
+
+struct Index {
+    bounds: Vec<u64>, // line i is bounds[i]..bounds[i + 1], newline included
+    done: bool,
+    replaced: usize,  // invalid sequences seen so far
+}
+
+impl LazyText {
+    pub fn new(file: File, encoding: Encoding) -> io::Result<Self> {
+        // the line index is built on a background thread, it stops once the text is dropped
+        let len = file.metadata()?.len();
+        let start = (encoding.bom_len() as u64).min(len);
+        let index = Arc::new(Mutex::new(Index { bounds: vec![start], done: false, replaced: 0 }));
+
+        let reader = file.try_clone()?;
+        let weak = Arc::downgrade(&index);
+        thread::spawn(move || LazyText::build(reader, len, encoding, weak));
+
+        Ok(LazyText { file, len, encoding, index })
+    }
+
+    fn build(file: File, len: u64, encoding: Encoding, index: Weak<Mutex<Index>>) {
+        let newline = encoding.newline();
+        let unit = encoding.unit();
+        let mut offset = (encoding.bom_len() as u64).min(len);
+        let mut buf = Vec::new();
+
+        while offset < len {
+            // chunks end on a unit boundary, so a utf-16 newline is never split
+            let mut size = INDEX_CHUNK.min(len - offset) as usize;
+            if offset + (size as u64) < len {
+                size -= size % unit;
+            }
+
+            buf.resize(size, 0);
+            if file.read_exact_at(&mut buf, offset).is_err() {
+                break;
+            }
+
+            let bounds: Vec<u64> = (0..size.saturating_sub(unit - 1))
+                .step_by(unit)
+                .filter(|&i| buf[i..].starts_with(newline))
+                .map(|i| offset + (i + unit) as u64)
+                .collect();
+
+            // the rest of the last line is read again with the next chunk, unless the line is huge
+            let end = match bounds.last() {
+                Some(&end) if offset + (size as u64) < len => end,
+                _ => offset + size as u64,
+            };
+
+            let replaced = match encoding {
+                Encoding::Utf8 { .. } => encoding::utf8_errors(&buf[..(end - offset) as usize]).1,
+                _ => 0,
+            };
+
+            let Some(index) = index.upgrade() else {
+                return;
+            };
+            let mut index = index.lock().unwrap();
+            index.bounds.extend(bounds.into_iter().filter(|&b| b <= end));
+            index.replaced += replaced;
+
+            offset = end;
+        }
+
+        if let Some(index) = index.upgrade() {
+            let mut index = index.lock().unwrap();
+            if index.bounds.last().is_some_and(|&last| last < len) {
+                index.bounds.push(len);
+            }
+            index.done = true;
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn indexed(&self) -> usize {
+        // lines known so far
+        self.index.lock().unwrap().bounds.len() - 1
+    }
+
+    pub fn line_count(&self) -> Option<usize> {
+        // None while the index is still being built
+        let index = self.index.lock().unwrap();
+        index.done.then(|| index.bounds.len() - 1)
+    }
+
+    pub fn replaced(&self) -> usize {
+        self.index.lock().unwrap().replaced
+    }
+
+    pub fn lines(&self, start: usize, end: usize) -> Vec<String> {
+        let bounds = {
+            let index = self.index.lock().unwrap();
+            let end = end.min(index.bounds.len() - 1);
+            index.bounds[start.min(end)..=end].to_vec()
+        };
+
+        bounds.windows(2).map(|line| self.line(line[0], line[1])).collect()
+    }
+
+    fn line(&self, start: u64, end: u64) -> String {
+        let cut = end - start > MAX_LINE;
+        let mut buf = vec![0; (end - start).min(MAX_LINE) as usize];
+        if let Err(e) = self.file.read_exact_at(&mut buf, start) {
+            return format!("{REPLACEMENT} {e}");
+        }
+
+        let mut line = encoding::decode(&buf, self.encoding.without_bom());
+        if cut {
+            // the cut may split a char
+            line.truncate(line.trim_end_matches(REPLACEMENT).len());
+            line.push('…');
+        }
+
+        let trimmed = line.strip_suffix('\n').unwrap_or(&line);
+        trimmed.strip_suffix('\r').unwrap_or(trimmed).to_string()
+    }
+
+    pub fn offset_of(&self, line: usize) -> u64 {
+        let index = self.index.lock().unwrap();
+        index.bounds[line.min(index.bounds.len() - 1)]
+    }
+
+    pub fn line_of(&self, offset: u64) -> usize {
+        let index = self.index.lock().unwrap();
+        index.bounds.partition_point(|&b| b <= offset).saturating_sub(1)
+    }
+
+    pub fn search(&self, query: &str, from: u64, forward: bool) -> io::Result<Search> {
+        // searches the raw bytes, the query is encoded like the file
+        let pattern = encoding::encode(query, self.encoding);
+        Search::spawn(&self.file, self.len, pattern, from, forward, self.encoding.unit() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::TempPath;
+    use std::time::Duration;
+
+    fn text(name: &str, bytes: &[u8], encoding: Encoding) -> LazyText {
+        let path = TempPath::file(&format!("text-{name}"), bytes);
+        let text = LazyText::new(File::open(&path).unwrap(), encoding).unwrap();
+
+        while text.line_count().is_none() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        text
+    }
+
+    fn find(text: &LazyText, query: &str) -> Option<u64> {
+        text.search(query, 0, true).unwrap().poll(Duration::from_secs(5)).unwrap()
+    }
+
+    #[test]
+    fn indexes_lines_like_str_lines() {
+        let text = text("utf8", b"one\r\ntwo\n\nfour", Encoding::Utf8 { bom: false });
+
+        assert_eq!(text.line_count(), Some(4));
+        assert_eq!(text.lines(0, 10), vec!["one", "two", "", "four"]);
+        assert_eq!(text.line_of(find(&text, "four").unwrap()), 3);
+    }
+
+    #[test]
+    fn reads_utf16_after_the_bom() {
+        let text = text("utf16", b"\xFF\xFEa\0\n\0b\0\n\0", Encoding::Utf16Le { bom: true });
+
+        assert_eq!(text.line_count(), Some(2));
+        assert_eq!(text.lines(1, 2), vec!["b"]);
+        assert_eq!(find(&text, "b"), Some(6));
+    }
+
+    #[test]
+    fn counts_replaced_chars() {
+        let text = text("bad", b"ok\n\xFF bad\n", Encoding::Utf8 { bom: false });
+
+        assert_eq!(text.replaced(), 1);
+        assert_eq!(text.lines(1, 2), vec!["\u{FFFD} bad"]);
+    }
+}
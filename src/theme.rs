@@ -0,0 +1,120 @@
+use std::{path::{Path, PathBuf}, str::FromStr};
+use ratatui::style::Color;
+use syntect::highlighting::{Theme, ThemeSet};
+use crate::highlight::THEME_SET;
+
+const DARK_THEME: &str = "base16-ocean.dark";
+const LIGHT_THEME: &str = "InspiredGitHub";
+
+// colors of the ui, the preview is colored by the syntax theme
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Colors {
+    pub fg: Color,        // text and unfocused borders
+    pub bg: Color,
+    pub highlight: Color, // the selected candidate
+    pub border: Color,    // the focused pane
+    pub matched: Color,   // matched chars of candidates
+    pub prompt: Color,    // the query
+    pub light: bool,      // picks the default syntax theme
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors::dark()
+    }
+}
+
+impl Colors {
+    pub fn dark() -> Self {
+        Colors {
+            fg: Color::White,
+            bg: Color::Reset,
+            highlight: Color::Yellow,
+            border: Color::Blue,
+            matched: Color::Green,
+            prompt: Color::Blue,
+            light: false,
+        }
+    }
+
+    pub fn light() -> Self {
+        Colors {
+            fg: Color::Black,
+            bg: Color::Reset,
+            highlight: Color::Magenta,
+            border: Color::Blue,
+            matched: Color::Red,
+            prompt: Color::Blue,
+            light: true,
+        }
+    }
+
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        // "light", "fg:#c0c5ce,match:red" or both, later entries override earlier ones
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            match entry.split_once(':') {
+                None if entry == "dark" => *self = Colors::dark(),
+                None if entry == "light" => *self = Colors::light(),
+                None => return Err(format!("unknown color scheme {entry}")),
+                Some((name, value)) => {
+                    let color = Color::from_str(value).map_err(|_| format!("invalid color {value}"))?;
+                    match name {
+                        "fg" => self.fg = color,
+                        "bg" => self.bg = color,
+                        "hl" | "highlight" => self.highlight = color,
+                        "border" => self.border = color,
+                        "match" => self.matched = color,
+                        "prompt" => self.prompt = color,
+                        _ => return Err(format!("unknown color {name}")),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn default_theme(&self) -> &'static Theme {
+        &THEME_SET.themes[if self.light {LIGHT_THEME} else {DARK_THEME}]
+    }
+}
+
+pub fn load_theme(name: &str, config_dir: Option<&Path>) -> Option<&'static Theme> {
+    // a bundled theme, config_dir/themes/<name>.tmTheme or a path to a .tmTheme file
+    if let Some(theme) = THEME_SET.themes.get(name) {
+        return Some(theme);
+    }
+
+    let in_config = config_dir.map(|dir| dir.join("themes").join(format!("{name}.tmTheme")));
+    let path = in_config.filter(|path| path.is_file()).unwrap_or_else(|| PathBuf::from(name));
+
+    // loaded once per run, so it can live as long as the bundled ones
+    let theme = ThemeSet::get_theme(path).ok()?;
+    Some(Box::leak(Box::new(theme)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_color_specs() {
+        let mut colors = Colors::default();
+        colors.apply("light,match:#ff0000,hl:2").unwrap();
+
+        assert_eq!(colors.fg, Color::Black);
+        assert_eq!(colors.matched, Color::Rgb(255, 0, 0));
+        assert_eq!(colors.highlight, Color::Indexed(2));
+        assert!(std::ptr::eq(colors.default_theme(), &THEME_SET.themes[LIGHT_THEME]));
+
+        assert_eq!(colors.apply("sepia"), Err("unknown color scheme sepia".to_string()));
+        assert_eq!(colors.apply("fg:nope"), Err("invalid color nope".to_string()));
+        assert_eq!(colors.apply("cursor:red"), Err("unknown color cursor".to_string()));
+    }
+
+    #[test]
+    fn loads_themes_by_name_or_path() {
+        assert!(load_theme("Solarized (light)", None).is_some());
+        assert!(load_theme("no such theme", Some(Path::new("/nonexistent"))).is_none());
+    }
+}
@@ -1,45 +1,135 @@
 use std::cmp::min;
 use std::fs::File;
-use std::io::{self, BufRead};
-use std::iter;
+use std::io::{self, Read};
+use std::time::Duration;
 
-use once_cell::sync::Lazy;
-use syntect::easy::HighlightLines;
-use syntect::parsing::SyntaxSet;
-use syntect::highlighting::ThemeSet;
-use syntect::util::as_24_bit_terminal_escaped;
-use syntect::highlighting::Style;
+use crate::encoding::{self, Encoding, REPLACEMENT, SAMPLE_SIZE};
+use syntect::parsing::SyntaxReference;
 
-static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(|| SyntaxSet::load_defaults_newlines());
-static THEME_SET: Lazy<ThemeSet> = Lazy::new(|| ThemeSet::load_defaults());
+use crate::highlight::{self, Highlighter};
+use crate::hexdump::{self, HexDump, Search, ROW_SIZE};
+use crate::text::LazyText;
+
+// bytes that couldn't be decoded are shown in reverse video
+const MARKED_REPLACEMENT: &str = "\x1b[7m\u{FFFD}\x1b[27m";
+const SEARCH_WAIT: Duration = Duration::from_millis(20); // small files are searched before the next frame
 
 
 pub struct Viewer {
     pub search_string: String,
     pub display_start: usize,
     search_results: Vec<SearchResult>,
-    file_content: Vec<String>,
-    file_extension: String,
+    source: Source,
+    syntax: Option<&'static SyntaxReference>,
     curr_search_idx: usize,
+    found: Option<u64>, // offset of the current match in files searched on demand
+    searching: Option<Search>, // runs in the background, the next match once it's done
+}
+
+enum Source {
+    Lines(Vec<String>), // e.g. a directory listing
+    Output(Vec<String>), // of the --preview command, may contain ansi colors
+    Text(LazyText),
+    Hex(HexDump),       // binary files, one line per 16 bytes
 }
 
 struct SearchResult {
     line_no: usize,
-    pos_in_line: usize,
 }
 
 impl Viewer {
+    pub fn encoding(&self) -> Option<Encoding> {
+        match &self.source {
+            Source::Text(text) => Some(text.encoding()),
+            _ => None,
+        }
+    }
+
+    pub fn replaced(&self) -> usize {
+        match &self.source {
+            Source::Text(text) => text.replaced(),
+            _ => 0,
+        }
+    }
+
+    pub fn line_count(&self) -> Option<usize> {
+        // None while a file is still being indexed
+        match &self.source {
+            Source::Text(text) => text.line_count(),
+            _ => Some(self.len()),
+        }
+    }
+
+    pub fn syntax(&self) -> Option<&'static SyntaxReference> {
+        // the ui keeps a Highlighter for it, syntect's parser state can't be sent between threads
+        self.syntax
+    }
+
+    pub fn is_hex(&self) -> bool {
+        matches!(self.source, Source::Hex(_))
+    }
+
+    fn len(&self) -> usize {
+        match &self.source {
+            Source::Lines(lines) | Source::Output(lines) => lines.len(),
+            Source::Text(text) => text.indexed(),
+            Source::Hex(dump) => dump.rows(),
+        }
+    }
+
+    fn find(&mut self, from: u64, forward: bool) {
+        // files are searched on demand, they may be too big to collect every match,
+        // a newer search cancels the running one
+        let search = match &self.source {
+            Source::Lines(_) | Source::Output(_) => return,
+            Source::Text(text) => text.search(&self.search_string, from, forward),
+            Source::Hex(dump) => dump.search(hexdump::parse_pattern(&self.search_string), from, forward),
+        };
+
+        self.searching = search.ok();
+        self.poll_search(SEARCH_WAIT);
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.searching.is_some()
+    }
+
+    pub fn poll_search(&mut self, wait: Duration) -> bool {
+        // true once the running search is done, it jumps to the match
+        let Some(found) = self.searching.as_ref().and_then(|search| search.poll(wait)) else {
+            return false;
+        };
+        self.searching = None;
+
+        if let Some(offset) = found {
+            self.found = Some(offset);
+            self.display_start = match &self.source {
+                Source::Text(text) => text.line_of(offset),
+                _ => (offset / ROW_SIZE) as usize,
+            };
+        }
+        true
+    }
+
     pub fn go_to_prev_search(&mut self) {
+        if let Some(offset) = self.found {
+            return self.find(offset.checked_sub(1).unwrap_or(u64::MAX), false);
+        }
+
         if self.search_results.is_empty() {
             return;
         }
         
         let m = self.search_results.len();
-        self.curr_search_idx = (((self.curr_search_idx - 1) % m) + m) % m;
+        self.curr_search_idx = (self.curr_search_idx + m - 1) % m;
         self.display_start = self.search_results[self.curr_search_idx].line_no;
     }
 
     pub fn go_to_next_search(&mut self) {
+        if let Some(offset) = self.found {
+            return self.find(offset + 1, true);
+        }
+
         if self.search_results.is_empty() {
             return;
         }
@@ -63,75 +153,112 @@ impl Viewer {
     }
 
     pub fn down_char(&mut self) {
-        if self.display_start + 1 < self.file_content.len() {
+        if self.display_start + 1 < self.len() {
             self.display_start += 1;
         }
     }
 
     pub fn search(&mut self) {
-        // updates search_resluts
+        // updates search_resluts, starting from the displayed line and wrapping around
         self.search_results.clear();
+        self.found = None;
+
+        let lines = match &self.source {
+            Source::Lines(lines) | Source::Output(lines) => lines,
+            Source::Text(text) => return self.find(text.offset_of(self.display_start), true),
+            // "0x7f45" or "7f 45" search for bytes
+            Source::Hex(_) => return self.find(self.display_start as u64 * ROW_SIZE, true),
+        };
 
-        for (line_idx, line) in self.file_content[self.display_start..self.file_content.len()].iter().enumerate() {
-            for (pos_in_line, _) in line.match_indices(&self.search_string).into_iter() {
-               self.search_results.push(SearchResult { line_no: line_idx, pos_in_line: pos_in_line }); 
+        let len = lines.len();
+        for line_no in (self.display_start..len).chain(0..self.display_start) {
+            for _ in lines[line_no].match_indices(&self.search_string) {
+               self.search_results.push(SearchResult { line_no }); 
             }
         }
-        
-        for (line_idx, line) in self.file_content[0..self.display_start].iter().enumerate() {
-            for (pos_in_line, _) in line.match_indices(&self.search_string).into_iter() {
-               self.search_results.push(SearchResult { line_no: line_idx, pos_in_line: pos_in_line }); 
-            }
+
+        // jump to the first match, like vim does
+        self.curr_search_idx = 0;
+        if let Some(result) = self.search_results.first() {
+            self.display_start = result.line_no;
         }
     }
 
-    pub fn new(file_name: &str) -> io::Result<Self> {
-        // read the file
+    pub fn new(file_name: &str, map_syntax: &[(String, String)]) -> io::Result<Self> {
+        // text is read lazily, binary files are shown as a hex dump
         let file = File::open(file_name)?;
-        let reader = io::BufReader::new(file);
-        let content: Vec<String> = reader
-            .lines()
-            .collect::<Result<Vec<_>, _>>()?;
-        
-        let file_extension = file_name.split('.').last().unwrap_or_else(|| "error: file name doesn't contains extension").to_string();
 
-        Ok(Viewer {
+        // the encoding is guessed from the first bytes
+        let mut head = Vec::with_capacity(SAMPLE_SIZE);
+        (&file).take(SAMPLE_SIZE as u64).read_to_end(&mut head)?;
+
+        let Some(encoding) = encoding::detect(&head) else {
+            return Ok(Viewer::from_source(Source::Hex(HexDump::new(file)?), None));
+        };
+
+        let text = encoding::decode(&head, encoding);
+        let syntax = highlight::detect(file_name, text.lines().next().unwrap_or_default(), map_syntax);
+
+        Ok(Viewer::from_source(Source::Text(LazyText::new(file, encoding)?), syntax))
+    }
+
+    pub fn from_lines(content: Vec<String>) -> Self {
+        // content that doesn't come from a file, e.g. a directory listing
+        Viewer::from_source(Source::Lines(content), None)
+    }
+
+    pub fn from_output(output: Vec<String>) -> Self {
+        Viewer::from_source(Source::Output(output), None)
+    }
+
+    fn from_source(source: Source, syntax: Option<&'static SyntaxReference>) -> Self {
+        Viewer {
             search_string: String::new(),
             search_results: Vec::new(),
-            file_content: content,
-            file_extension: file_extension,
+            source,
+            syntax,
             display_start: 0,
             curr_search_idx: 0,
-        })
+            found: None,
+            searching: None,
+        }
     }
 
-    pub fn get_lines(&mut self, start: usize, ammount: usize) -> (Vec<String>, bool) {
-        let end = (start + ammount).min(self.file_content.len() - 1);
-        self.display_start = min(self.display_start, self.file_content.len());
-        
-        let content = match SYNTAX_SET.find_syntax_by_extension(&self.file_extension) {
-            Some(syntax) => {
-                let mut h = HighlightLines::new(syntax, &THEME_SET.themes["base16-ocean.dark"]);
-                let mut styled_content = Vec::<String>::new();
-
-                for line in &self.file_content[start..end] {
-                    let ranges: Vec<(Style, &str)> = h.highlight_line(&line, &SYNTAX_SET).unwrap();
-                    let escaped = as_24_bit_terminal_escaped(&ranges[..], false);
-                    styled_content.push(escaped); 
-                }
-
-                (styled_content, true)
-            },
-            None => {
-                let mut plane_content = Vec::<String>::new();
-                for line in &self.file_content[start..end] {
-                    plane_content.push(line.to_string())
-                }
-
-                (plane_content, false)
-            }
+    fn window(source: &Source, start: usize, end: usize) -> Vec<String> {
+        match source {
+            Source::Lines(lines) | Source::Output(lines) => lines[start.min(lines.len())..end.min(lines.len())].to_vec(),
+            Source::Text(text) => text.lines(start, end),
+            Source::Hex(_) => Vec::new(),
+        }
+    }
+
+    pub fn get_lines(&mut self, start: usize, ammount: usize, highlighter: Option<&mut Highlighter>) -> (Vec<String>, bool) {
+        // only the requested window is read and decoded
+        let end = (start + ammount).min(self.len());
+        let start = min(start, end);
+        self.display_start = min(self.display_start, self.len());
+
+        let lines = match &mut self.source {
+            Source::Hex(dump) => return ((start..end).map(|row| dump.row(row)).collect(), false),
+            Source::Output(lines) => return (lines[start..end].to_vec(), true),
+            source => Viewer::window(source, start, end),
+        };
+
+        // highlighting starts from a cached parser state near the window, it's plain until there is one
+        let source = &self.source;
+        let (lines, colored) = match highlighter.and_then(|highlighter| highlighter.highlight(start, &lines, |a, b| Viewer::window(source, a, b))) {
+            Some(highlighted) => (highlighted, true),
+            None => (lines, false),
         };
 
-        return content
+        match lines.iter().any(|line| line.contains(REPLACEMENT)) {
+            false => (lines, colored),
+            true => (lines.into_iter().map(|line| line.replace(REPLACEMENT, MARKED_REPLACEMENT)).collect(), true),
+        }
+    }
+
+    pub fn highlight_ahead(&self, highlighter: &mut Highlighter) -> bool {
+        // true once the window get_lines showed plain can be colored
+        highlighter.advance(|a, b| Viewer::window(&self.source, a, b))
     }
 }